@@ -1,24 +1,57 @@
-use sdl2::rect::{Rect, Point};
-use specs::{VecStorage, NullStorage, HashMapStorage};
+use std::collections::HashMap;
+
+use sdl2::rect::Rect;
+use specs::{Entity, VecStorage, NullStorage, HashMapStorage, FlaggedStorage};
+use nalgebra::Point2;
 
 use texture_manager::TextureId;
+use level_file::PropertyValue;
 use math::{Vec2D};
 
 /// Represents the XY world coordinates of the center of an entity.
 ///
 /// This is distinct from the screen coordinates which are bounded by the size of the display.
+/// Positions are not limited to whole pixels. Only the renderer rounds them.
 ///
 /// Not to be modified outside of the physics system.
-#[derive(Debug, Component)]
+#[derive(Debug, Clone, Component)]
+#[storage(VecStorage)]
+pub struct Position(pub Vec2D);
+
+/// The Position of an entity at the end of the previous frame. Used by the renderer to smoothly
+/// interpolate between the previous and current positions when rendering between frames.
+///
+/// Added and updated automatically by the physics system.
+#[derive(Debug, Clone, Component)]
 #[storage(VecStorage)]
-pub struct Position(pub Point);
+pub struct PreviousPosition(pub Vec2D);
+
+/// The angle (radians) that an entity is rotated clockwise around its center. Optional, since most
+/// entities never rotate. Sprites are drawn rotated by this angle.
+///
+/// Synced from the physics engine by the physics system, which also uses it as the starting angle
+/// of a rigid body. Not to be modified outside of the physics system.
+#[derive(Debug, Clone, Default, Component)]
+#[storage(HashMapStorage)]
+pub struct Rotation(pub f64);
+
+/// Keeps a rigid body from rotating, no matter what hits it. Used for characters that should
+/// always stay upright.
+///
+/// Adding or removing this after the entity is added to the physics engine is currently NOT
+/// supported.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct LockRotation;
 
 /// Represents the bounding box centered around an entity's position. BoundingBox alone doesn't
 /// mean much without a Position also attached to the entity.
 ///
-/// Modifying this after it is initially set is currently NOT supported.
-#[derive(Debug, Component)]
-#[storage(VecStorage)]
+/// Removing this component (or deleting the entity) removes the entity from the physics engine.
+/// Modifying it rebuilds the entity's collider, mass and sensors while keeping its position and
+/// velocity.
+#[derive(Debug, Clone, Component)]
+#[storage(FlaggedStorage)]
 pub struct BoundingBox {
     pub width: u32,
     pub height: u32,
@@ -27,10 +60,152 @@ pub struct BoundingBox {
 /// Represents the density of an entity. Entities without a specified density are assumed to be
 /// static. Such entities can never move and are used as ground colliders for rigid body entities.
 ///
+/// Adding, modifying or removing this rebuilds the entity in the physics engine while keeping its
+/// position and velocity.
+#[derive(Debug, Clone, Component)]
+#[storage(FlaggedStorage)]
+pub struct Density(pub f64);
+
+/// Marks an entity as a kinematic body. Kinematic bodies are not affected by gravity, forces or
+/// collisions. Instead, they move at whatever Velocity they are given (usually by PathFollower).
+/// Rigid bodies collide with kinematic bodies as if they were static and anything standing on a
+/// kinematic body is carried along with it.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct Kinematic;
+
+/// The name of the collision layer that an entity is on. The map decides which layers collide,
+/// only overlap or ignore each other (see CollisionRules). Entities without a layer collide with
+/// everything.
+///
+/// Adding, modifying or removing this rebuilds the entity in the physics engine.
+#[derive(Debug, Clone, Component)]
+#[storage(FlaggedStorage)]
+pub struct CollisionLayer(pub String);
+
+/// How a PathFollower continues once it reaches the end of its path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathMode {
+    /// Go back along the path in the reverse direction
+    PingPong,
+    /// Go from the end of the path straight back to the start
+    Loop,
+}
+
+/// Moves a Kinematic entity along a path at a constant speed by setting its Velocity
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct PathFollower {
+    /// The points along the path in world coordinates. Must have at least two points.
+    pub path: Vec<Vec2D>,
+    /// Unit: pixels / second
+    pub speed: f64,
+    pub mode: PathMode,
+    /// The number of frames to wait after reaching each point on the path
+    pub wait_frames: usize,
+    /// The index of the point currently being moved towards
+    pub next_point: usize,
+    /// True if currently moving from the end of the path back to its start (PingPong only)
+    pub reversed: bool,
+    /// The number of frames left to wait before moving again
+    pub wait_counter: usize,
+}
+
+impl PathFollower {
+    /// Creates a path follower that starts at the first point in the path
+    pub fn new(path: Vec<Vec2D>, speed: f64, mode: PathMode, wait_frames: usize) -> Self {
+        assert!(path.len() >= 2, "A path must have at least two points");
+
+        Self {
+            path,
+            speed,
+            mode,
+            wait_frames,
+            next_point: 1,
+            reversed: false,
+            wait_counter: 0,
+        }
+    }
+}
+
+/// The region of the world covered by a Trigger, in world coordinates
+#[derive(Debug, Clone)]
+pub enum TriggerShape {
+    Rect {
+        center: Vec2D,
+        width: f64,
+        height: f64,
+    },
+    /// Non-convex polygons are treated as if they were their convex hull
+    Polygon(Vec<Point2<f64>>),
+}
+
+/// An invisible region of the world. The physics system publishes a TriggerEvent whenever
+/// another entity enters, stays in or exits the region.
+#[derive(Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Trigger {
+    /// The name of the trigger from the map, used to decide what the trigger does
+    pub name: String,
+    pub shape: TriggerShape,
+    /// Any custom properties set on the trigger in the map
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+/// The friction coefficient of an entity's surface. Must be between 0.0 and 1.0.
+///
+/// Rigid bodies without a specified friction are frictionless. Static colliders without a
+/// specified friction use a default friction of 0.5.
+///
 /// Modifying this after it is initially set is currently NOT supported.
 #[derive(Debug, Component)]
 #[storage(HashMapStorage)]
-pub struct Density(pub f64);
+pub struct Friction(pub f64);
+
+/// The restitution (bounciness) of an entity's surface. Must be between 0.0 and 1.0.
+/// A restitution of 0.0 means that nothing bounces off of the entity. Entities without a specified
+/// restitution do not bounce.
+///
+/// Modifying this after it is initially set is currently NOT supported.
+#[derive(Debug, Component)]
+#[storage(HashMapStorage)]
+pub struct Restitution(pub f64);
+
+/// A small, fast entity that moves in a straight line at its Velocity without being affected by
+/// gravity. Projectiles are not bodies in the physics engine (so they should not have a
+/// BoundingBox). Instead, the physics system casts a ray along the path of each projectile every
+/// frame so that it can never pass through anything without hitting it. Projectiles pass through
+/// one-way boundaries and anything that their owner's CollisionLayer doesn't collide with.
+///
+/// A ProjectileHit event is published for everything the projectile hits. The projectile is
+/// deleted once it hits something that it can't pierce or its lifetime runs out.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct Projectile {
+    /// The number of frames left before the projectile is deleted
+    pub lifetime: usize,
+    /// The damage dealt to anything the projectile hits
+    pub damage: f64,
+    /// The entity that fired the projectile, if any. Projectiles never hit their owner.
+    pub owner: Option<Entity>,
+    /// The number of entities the projectile can still pass through after hitting them. The
+    /// static geometry of the map can never be pierced.
+    pub pierce: usize,
+    /// The entities that have already been hit. Each entity is only hit once.
+    pub hit: Vec<Entity>,
+}
+
+impl Projectile {
+    pub fn new(lifetime: usize, damage: f64, owner: Option<Entity>, pierce: usize) -> Self {
+        Self {
+            lifetime,
+            damage,
+            owner,
+            pierce,
+            hit: Vec::new(),
+        }
+    }
+}
 
 /// The current velocity of an entity. Usually not manipulated directory by anything other
 /// than the physics engine. Use AppliedForce to move entities instead.
@@ -38,8 +213,8 @@ pub struct Density(pub f64);
 /// Keep in mind that the coordinate system has +x to the right and +y downwards.
 /// Unit: pixels / frame
 ///
-/// Not to be modified outside of the physics system.
-#[derive(Debug, Component)]
+/// Not to be modified outside of the physics system, unless the entity is Kinematic.
+#[derive(Debug, Clone, Component)]
 #[storage(VecStorage)]
 pub struct Velocity(pub Vec2D);
 
@@ -48,12 +223,12 @@ pub struct Velocity(pub Vec2D);
 ///
 /// Keep in mind that the coordinate system has +x to the right and +y downwards.
 /// Unit: pixels / frame^2
-#[derive(Debug, Component)]
+#[derive(Debug, Clone, Component)]
 #[storage(HashMapStorage)]
 pub struct AppliedAcceleration(pub Vec2D);
 
 /// Whether anything is touching a given entity in any of the cardinal directions
-#[derive(Debug, Default, Component)]
+#[derive(Debug, Clone, Default, Component)]
 #[storage(HashMapStorage)]
 pub struct Collisons {
     pub top: bool,
@@ -62,11 +237,120 @@ pub struct Collisons {
     pub bottom: bool,
 }
 
+/// The ground that an entity is standing on. Only present while the entity is touching something
+/// below it.
+///
+/// Added, updated and removed automatically by the physics system for entities with Collisons.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct GroundContact {
+    /// Unit vector pointing away from the surface of the ground (towards the entity). Since +y is
+    /// downwards, this always has a negative y component.
+    pub normal: Vec2D,
+    /// Angle of the ground from horizontal (radians)
+    pub slope: f64,
+    /// True if the ground is not too steep to stand on without sliding down
+    pub walkable: bool,
+    /// The entity that is the ground, or None for the static geometry of the map
+    pub entity: Option<Entity>,
+}
+
+impl GroundContact {
+    /// Creates the ground contact for ground with the given normal. Ground steeper than the given
+    /// maximum slope (radians) is not walkable.
+    pub fn new(normal: Vec2D, entity: Option<Entity>, max_walkable_slope: f64) -> Self {
+        let slope = (-normal.y).min(1.0).acos();
+        Self {
+            normal,
+            slope,
+            walkable: slope <= max_walkable_slope,
+            entity,
+        }
+    }
+}
+
+/// Makes an entity drop down through any one-way boundaries it is currently standing on.
+/// Removed by the physics system once it has been handled.
+#[derive(Debug, Clone, Default, Component)]
+#[storage(NullStorage)]
+pub struct DropDown;
+
+/// Marks an entity that is too far outside of the camera's view to be worth simulating. Suspended
+/// entities are not moved by the physics engine or by any system that controls them. Added and
+/// removed by the Culling system.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct Suspended;
+
 /// The keyboard controlled player. Only one entity should hold this at a given time.
 #[derive(Debug, Default, Component)]
 #[storage(NullStorage)]
 pub struct KeyboardControlled;
 
+/// Tuning values for how JumpControl entities jump and hover. The same tuning (from
+/// MovementConfig) applies to every entity. All accelerations are upwards unless otherwise
+/// specified.
+///
+/// Unit of accelerations: pixels / second^2
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JumpTuning {
+    /// The acceleration applied (in addition to overcoming gravity) on the frame a jump starts
+    pub start_accel: f64,
+    /// The acceleration applied on each following frame that the jump button is still held
+    pub hold_accel: f64,
+    /// The most frames that hold_accel can be applied for in a single jump
+    pub max_hold_frames: usize,
+    /// Downward acceleration applied while still moving up after the jump button is released.
+    /// This cuts the jump short.
+    pub release_accel: f64,
+    /// The fraction of gravity that still applies while hovering
+    pub hover_gravity_scale: f64,
+    /// The number of frames the entity can hover for before it needs to land again
+    pub max_hover_frames: usize,
+    /// The number of frames after walking off a ledge during which a jump is still allowed
+    pub coyote_frames: usize,
+    /// The number of frames before landing during which pressing jump results in a jump as soon
+    /// as the entity lands
+    pub jump_buffer_frames: usize,
+}
+
+impl Default for JumpTuning {
+    fn default() -> Self {
+        Self {
+            start_accel: 4000.0,
+            hold_accel: 400.0,
+            max_hold_frames: 10,
+            release_accel: 300.0,
+            hover_gravity_scale: 0.3,
+            max_hover_frames: 60,
+            coyote_frames: 6,
+            jump_buffer_frames: 6,
+        }
+    }
+}
+
+/// Lets a keyboard controlled entity jump higher by holding the jump button and hover by holding
+/// it while falling. Keyboard controlled entities also need a Velocity and Collisons in order to
+/// be controlled. How high and how long the entity jumps is set by the jump tuning in
+/// MovementConfig.
+#[derive(Debug, Clone, Default, Component)]
+#[storage(HashMapStorage)]
+pub struct JumpControl {
+    /// The number of frames the current jump has been held for, or None if not currently in the
+    /// rising part of a jump
+    pub hold_frames: Option<usize>,
+    /// The number of frames of hovering left before the entity needs to land again. Refilled
+    /// every time the entity lands.
+    pub hover_frames_left: usize,
+    /// True if the jump button was held during the previous frame
+    pub button_held: bool,
+    /// The number of frames left in which a jump can still start despite not being on the ground
+    pub coyote_frames_left: usize,
+    /// The number of frames left in which a jump that was pressed too early can still start
+    pub buffered_frames_left: usize,
+}
+
 /// The entity with this component and a Position component will be centered in the camera
 /// when the scene is rendered.
 /// Only one entity should hold this at a given time.
@@ -78,7 +362,7 @@ pub struct CameraFocus;
 ///
 /// The convention is that the sprite begins pointing to the right and flipping it horizontally
 /// results in it facing left
-#[derive(Debug, Component)]
+#[derive(Debug, Clone, Component)]
 #[storage(VecStorage)]
 pub struct Sprite {
     /// The spritesheet to pull the image from
@@ -89,7 +373,7 @@ pub struct Sprite {
     pub flip_horizontal: bool,
 }
 
-#[derive(Debug, Default, Component)]
+#[derive(Debug, Clone, Default, Component)]
 #[storage(HashMapStorage)]
 pub struct MovementAnimation {
     pub steps: Vec<(TextureId, Rect)>,
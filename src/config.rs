@@ -0,0 +1,293 @@
+//! Gameplay constants that can be tuned without recompiling the game. Loaded from a JSON file
+//! that is reloaded whenever it changes while the game is running.
+
+use std::{
+    io,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    collections::HashMap,
+    time::SystemTime,
+};
+
+use serde_json;
+
+use components::JumpTuning;
+use level_file::PropertyValue;
+
+/// Resource with the constants used by the physics engine (or the character controller)
+///
+/// Unit of accelerations: pixels / second^2
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// The acceleration of everything downwards due to gravity
+    pub gravity_accel: f64,
+    /// The distance (in pixels) around each collider in which contacts are already detected.
+    /// Colliders are shrunk by this much so they still appear to be their original size. Only
+    /// applies to bodies added after it changes.
+    pub collider_margin: f64,
+    /// The thickness (in pixels) of the sensors used to detect what each side of a body touches.
+    /// Only applies to bodies added after it changes.
+    pub sensor_size: f64,
+    /// The length of each sensor as a fraction of the side of the body it is on. Sensors need to
+    /// be a little shorter than the side so that they don't falsely detect collisions on the
+    /// corners of the bounding box. Only applies to bodies added after it changes.
+    pub sensor_scale_factor: f64,
+    /// The energy below which a body at rest is put to sleep until something wakes it up, or None
+    /// to never put bodies to sleep. Only applies to bodies added after it changes.
+    pub sleep_threshold: Option<f64>,
+    /// How far (in pixels) outside of the camera's view entities are still simulated. Anything
+    /// further away is suspended until the camera gets close enough again.
+    pub activation_margin: f64,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity_accel: 150.0,
+            collider_margin: 0.01,
+            sensor_size: 2.0,
+            sensor_scale_factor: 0.8,
+            sleep_threshold: Some(0.01),
+            activation_margin: 320.0,
+        }
+    }
+}
+
+/// Resource with the constants that control how keyboard controlled entities move
+///
+/// Unit of accelerations: pixels / second^2
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementConfig {
+    /// The fastest that walking can move an entity left or right (pixels / second)
+    pub max_walk_speed: f64,
+    /// The acceleration applied while walking until max_walk_speed is reached
+    pub walk_accel: f64,
+    /// The acceleration that slows an entity down once it stops walking, or that brings it back
+    /// down to max_walk_speed if it is going faster than that
+    pub walk_decel: f64,
+    /// The fraction (from 0.0 to 1.0) of walk_accel and walk_decel that applies while in the air
+    pub air_control: f64,
+    /// How many times walk_accel is used while walking in the opposite direction of the current
+    /// movement. Makes turning around feel responsive.
+    pub turn_around_boost: f64,
+    /// How every keyboard controlled entity jumps
+    pub jump: JumpTuning,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            max_walk_speed: 90.0,
+            walk_accel: 300.0,
+            walk_decel: 500.0,
+            air_control: 0.6,
+            turn_around_boost: 2.0,
+            jump: JumpTuning::default(),
+        }
+    }
+}
+
+impl MovementConfig {
+    /// Returns the horizontal acceleration that moves an entity with the given horizontal
+    /// velocity towards max_walk_speed in the given direction (-1.0 for left, 1.0 for right or
+    /// 0.0 to stop). The acceleration never overshoots the target speed within a single frame of
+    /// the given length (seconds).
+    pub fn horizontal_accel(&self, vel_x: f64, direction: f64, on_ground: bool, timestep: f64) -> f64 {
+        let target = direction * self.max_walk_speed;
+        let diff = target - vel_x;
+
+        let slowing_down = direction == 0.0 || diff * direction < 0.0;
+        let rate = if slowing_down {
+            self.walk_decel
+        }
+        else if vel_x * direction < 0.0 {
+            self.walk_accel * self.turn_around_boost
+        }
+        else {
+            self.walk_accel
+        };
+        let rate = if on_ground { rate } else { rate * self.air_control };
+
+        (diff / timestep).max(-rate).min(rate)
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum ReadConfigError {
+    #[fail(display = "failed to deserialize config file")]
+    SerdeError(#[cause] serde_json::error::Error),
+    #[fail(display = "IO error occurred while reading config file")]
+    IOError(#[cause] io::Error),
+    #[fail(display = "level property '{}' does not override anything in the config", name)]
+    UnknownOverride {
+        name: String,
+    },
+    #[fail(display = "level property '{}' is not a valid value for the config", name)]
+    InvalidOverride {
+        name: String,
+        #[cause]
+        error: serde_json::error::Error,
+    },
+}
+
+impl From<serde_json::error::Error> for ReadConfigError {
+    fn from(err: serde_json::error::Error) -> Self {
+        ReadConfigError::SerdeError(err)
+    }
+}
+
+impl From<io::Error> for ReadConfigError {
+    fn from(err: io::Error) -> Self {
+        ReadConfigError::IOError(err)
+    }
+}
+
+/// Every section of the config file. Anything missing from the file keeps its default value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub physics: PhysicsConfig,
+    pub movement: MovementConfig,
+}
+
+impl GameConfig {
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, ReadConfigError> {
+        let file = File::open(path)?;
+        let config = serde_json::from_reader(file)?;
+        Ok(config)
+    }
+
+    /// Overrides values in the config with the properties of a level. A property overrides the
+    /// value at the same path in the config file, with each part of the path separated by a dot
+    /// (e.g. `physics.gravity_accel` or `movement.jump.start_accel`). Properties that don't start
+    /// with the name of a section are not overrides and are ignored.
+    pub fn with_overrides(self, properties: &HashMap<String, PropertyValue>) -> Result<Self, ReadConfigError> {
+        let mut config = serde_json::to_value(&self)?;
+
+        // Sorted so that the same error is always reported first
+        let mut names: Vec<_> = properties.keys()
+            .filter(|name| name.starts_with("physics.") || name.starts_with("movement."))
+            .collect();
+        names.sort();
+        for name in names {
+            let pointer = format!("/{}", name.replace('.', "/"));
+            let value = match config.pointer_mut(&pointer) {
+                // Only individual values can be overridden, not whole sections
+                Some(value) if !value.is_object() => value,
+                _ => return Err(ReadConfigError::UnknownOverride {name: name.clone()}),
+            };
+            *value = serde_json::to_value(&properties[name])?;
+
+            // Checked after each override so that the error names the property that caused it
+            serde_json::from_value::<Self>(config.clone())
+                .map_err(|error| ReadConfigError::InvalidOverride {name: name.clone(), error})?;
+        }
+
+        Ok(serde_json::from_value(config)?)
+    }
+}
+
+/// Loads the config file along with the overrides from the level and keeps track of when the
+/// file changes so it can be reloaded
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    overrides: HashMap<String, PropertyValue>,
+    /// The last modified time of the file when it was last loaded
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new<P: AsRef<Path>>(path: P, overrides: HashMap<String, PropertyValue>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            overrides,
+            modified: None,
+        }
+    }
+
+    /// Loads the config file and applies the overrides
+    pub fn load(&mut self) -> Result<GameConfig, ReadConfigError> {
+        // Recorded even if loading fails so that a broken file is only reported once
+        self.modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        GameConfig::load_file(&self.path)?.with_overrides(&self.overrides)
+    }
+
+    /// Loads the config file again if it has changed since it was last loaded. Returns None if
+    /// it did not change.
+    pub fn reload_if_changed(&mut self) -> Option<Result<GameConfig, ReadConfigError>> {
+        let modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        Some(self.load())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTEP: f64 = 1.0 / 60.0;
+
+    #[test]
+    fn accelerates_up_to_max_walk_speed() {
+        let config = MovementConfig::default();
+
+        assert_eq!(config.horizontal_accel(0.0, 1.0, true, TIMESTEP), config.walk_accel);
+        assert_eq!(config.horizontal_accel(0.0, -1.0, true, TIMESTEP), -config.walk_accel);
+
+        // Walking for long enough reaches max_walk_speed without ever going past it
+        let mut vel_x = 0.0;
+        for _ in 0..120 {
+            vel_x += config.horizontal_accel(vel_x, 1.0, true, TIMESTEP) * TIMESTEP;
+            assert!(vel_x <= config.max_walk_speed + 1e-9);
+        }
+        assert!((vel_x - config.max_walk_speed).abs() < 1e-9);
+    }
+
+    #[test]
+    fn decelerates_to_a_stop_with_no_direction() {
+        let config = MovementConfig::default();
+
+        assert_eq!(config.horizontal_accel(50.0, 0.0, true, TIMESTEP), -config.walk_decel);
+        assert_eq!(config.horizontal_accel(-50.0, 0.0, true, TIMESTEP), config.walk_decel);
+
+        let mut vel_x = config.max_walk_speed;
+        for _ in 0..120 {
+            vel_x += config.horizontal_accel(vel_x, 0.0, true, TIMESTEP) * TIMESTEP;
+            assert!(vel_x >= -1e-9);
+        }
+        assert!(vel_x.abs() < 1e-9);
+    }
+
+    #[test]
+    fn brakes_when_faster_than_max_walk_speed() {
+        let config = MovementConfig::default();
+
+        let vel_x = config.max_walk_speed + 30.0;
+        assert_eq!(config.horizontal_accel(vel_x, 1.0, true, TIMESTEP), -config.walk_decel);
+        assert_eq!(config.horizontal_accel(-vel_x, -1.0, true, TIMESTEP), config.walk_decel);
+    }
+
+    #[test]
+    fn boosts_acceleration_when_turning_around() {
+        let config = MovementConfig::default();
+
+        let boosted = config.walk_accel * config.turn_around_boost;
+        assert_eq!(config.horizontal_accel(-50.0, 1.0, true, TIMESTEP), boosted);
+        assert_eq!(config.horizontal_accel(50.0, -1.0, true, TIMESTEP), -boosted);
+    }
+
+    #[test]
+    fn scales_acceleration_by_air_control_off_the_ground() {
+        let config = MovementConfig::default();
+
+        assert_eq!(config.horizontal_accel(0.0, 1.0, false, TIMESTEP), config.walk_accel * config.air_control);
+        assert_eq!(config.horizontal_accel(50.0, 0.0, false, TIMESTEP), -config.walk_decel * config.air_control);
+        assert_eq!(config.horizontal_accel(-50.0, 1.0, false, TIMESTEP),
+            config.walk_accel * config.turn_around_boost * config.air_control);
+    }
+}
@@ -0,0 +1,345 @@
+//! Maps the keys on the keyboard and the buttons on game controllers to the logical keys in
+//! GameKeys. Loaded from a JSON file that binds any number of keys and buttons to each logical key.
+
+use std::{
+    io::{self, Read},
+    fs::File,
+    path::Path,
+    collections::HashMap,
+};
+
+use sdl2::{
+    GameControllerSubsystem,
+    event::Event,
+    keyboard::{KeyboardState, Scancode},
+    controller::{GameController, Button, Axis},
+};
+use serde_json;
+
+use resources::GameKeys;
+
+/// The keys and buttons bound to each logical key when the bindings file doesn't say otherwise.
+/// Includes the keys sent by the GameShell keypad (https://github.com/clockworkpi/Keypad#keymaps)
+/// along with some that are easier to reach on a desktop keyboard. Controller buttons are bound by
+/// where they are on the controller, so the bottom button (A on most controllers) jumps just like
+/// the bottom button (B) of the GameShell.
+const DEFAULT_BINDINGS: &[(&str, &[Scancode], &[Button])] = &[
+    ("up_arrow", &[Scancode::Up, Scancode::W], &[Button::DPadUp]),
+    ("down_arrow", &[Scancode::Down, Scancode::S], &[Button::DPadDown]),
+    ("left_arrow", &[Scancode::Left, Scancode::A], &[Button::DPadLeft]),
+    ("right_arrow", &[Scancode::Right, Scancode::D], &[Button::DPadRight]),
+    ("menu", &[Scancode::Escape], &[Button::Guide]),
+    ("select", &[Scancode::Space], &[Button::Back]),
+    ("start", &[Scancode::Return], &[Button::Start]),
+    ("volume_down", &[Scancode::KpMinus], &[]),
+    ("volume_up", &[Scancode::KpPlus], &[]),
+    ("x", &[Scancode::I, Scancode::C], &[Button::Y]),
+    ("y", &[Scancode::U, Scancode::V], &[Button::X]),
+    ("a", &[Scancode::K, Scancode::X], &[Button::B]),
+    ("b", &[Scancode::J, Scancode::Z], &[Button::A]),
+    ("light_key_1", &[Scancode::H], &[Button::LeftShoulder]),
+    ("light_key_2", &[Scancode::Y], &[]),
+    //FIXME: No way to check if Shift key pressed
+    ("light_key_3", &[], &[]),
+    ("light_key_4", &[Scancode::O], &[]),
+    ("light_key_5", &[Scancode::L], &[Button::RightShoulder]),
+];
+
+/// How far (as a fraction of the way to the edge) an analog stick needs to be pushed before it
+/// counts as pressing an arrow key
+const DEFAULT_STICK_DEAD_ZONE: f64 = 0.4;
+
+#[derive(Debug, Fail)]
+pub enum ReadBindingsError {
+    #[fail(display = "failed to deserialize bindings file")]
+    SerdeError(#[cause] serde_json::error::Error),
+    #[fail(display = "IO error occurred while reading bindings file")]
+    IOError(#[cause] io::Error),
+    #[fail(display = "'{}' is not one of the keys in GameKeys", name)]
+    UnknownGameKey {
+        name: String,
+    },
+    #[fail(display = "'{}' (bound to '{}') is not the name of a key", key, name)]
+    UnknownKey {
+        name: String,
+        key: String,
+    },
+    #[fail(display = "'{}' (bound to '{}') is not the name of a game controller button", button, name)]
+    UnknownButton {
+        name: String,
+        button: String,
+    },
+    #[fail(display = "the stick dead zone must be at least 0.0 and less than 1.0, not {}", _0)]
+    InvalidDeadZone(f64),
+}
+
+impl From<serde_json::error::Error> for ReadBindingsError {
+    fn from(err: serde_json::error::Error) -> Self {
+        ReadBindingsError::SerdeError(err)
+    }
+}
+
+impl From<io::Error> for ReadBindingsError {
+    fn from(err: io::Error) -> Self {
+        ReadBindingsError::IOError(err)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingsFile {
+    /// The SDL names of the keys (e.g. "Left Shift" or "Keypad +") bound to each logical key
+    #[serde(default)]
+    keys: HashMap<String, Vec<String>>,
+    /// The SDL names of the game controller buttons (e.g. "a" or "dpup") bound to each logical key
+    #[serde(default)]
+    buttons: HashMap<String, Vec<String>>,
+    stick_dead_zone: Option<f64>,
+}
+
+/// The keys and buttons bound to each logical key in GameKeys. A logical key is pressed when any
+/// of the keys or buttons bound to it are pressed. The left stick of every game controller also
+/// presses the arrow keys.
+#[derive(Debug, Clone)]
+pub struct InputBindings {
+    keys: HashMap<&'static str, Vec<Scancode>>,
+    buttons: HashMap<&'static str, Vec<Button>>,
+    /// How far (from 0.0 to 1.0) a stick needs to be pushed to press an arrow key
+    stick_dead_zone: f64,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            keys: DEFAULT_BINDINGS.iter()
+                .map(|&(name, scancodes, _)| (name, scancodes.to_vec()))
+                .collect(),
+            buttons: DEFAULT_BINDINGS.iter()
+                .map(|&(name, _, buttons)| (name, buttons.to_vec()))
+                .collect(),
+            stick_dead_zone: DEFAULT_STICK_DEAD_ZONE,
+        }
+    }
+}
+
+impl InputBindings {
+    /// Loads the bindings from a file. Any logical key missing from a section of the file keeps
+    /// its default bindings for that section.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, ReadBindingsError> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    fn from_reader<R: Read>(reader: R) -> Result<Self, ReadBindingsError> {
+        let BindingsFile {keys, buttons, stick_dead_zone} = serde_json::from_reader(reader)?;
+
+        let mut bindings = Self::default();
+        // Sorted so that the same error is always reported first
+        let mut names: Vec<_> = keys.keys().collect();
+        names.sort();
+        for name in names {
+            let scancodes: Vec<_> = keys[name].iter()
+                .map(|key| Scancode::from_name(key).ok_or_else(|| ReadBindingsError::UnknownKey {
+                    name: name.clone(),
+                    key: key.clone(),
+                }))
+                .collect::<Result<_, _>>()?;
+            bindings.keys.insert(game_key(name)?, scancodes);
+        }
+
+        let mut names: Vec<_> = buttons.keys().collect();
+        names.sort();
+        for name in names {
+            let controller_buttons: Vec<_> = buttons[name].iter()
+                .map(|button| Button::from_string(button).ok_or_else(|| ReadBindingsError::UnknownButton {
+                    name: name.clone(),
+                    button: button.clone(),
+                }))
+                .collect::<Result<_, _>>()?;
+            bindings.buttons.insert(game_key(name)?, controller_buttons);
+        }
+
+        if let Some(dead_zone) = stick_dead_zone {
+            if dead_zone < 0.0 || dead_zone >= 1.0 {
+                return Err(ReadBindingsError::InvalidDeadZone(dead_zone));
+            }
+            bindings.stick_dead_zone = dead_zone;
+        }
+
+        Ok(bindings)
+    }
+
+    /// Returns which logical keys are pressed based on which keys on the keyboard and which
+    /// buttons on every connected game controller are pressed
+    pub fn game_keys(&self, keyboard: &KeyboardState, controllers: &Controllers) -> GameKeys {
+        let pressed = |name: &str| {
+            self.keys[name].iter().any(|&scancode| keyboard.is_scancode_pressed(scancode))
+                || controllers.any_pressed(&self.buttons[name])
+        };
+        // Positive y is downwards on the sticks too
+        let stick = |axis, direction| controllers.any_pushed(axis, direction, self.stick_dead_zone);
+
+        GameKeys {
+            up_arrow: pressed("up_arrow") || stick(Axis::LeftY, -1.0),
+            down_arrow: pressed("down_arrow") || stick(Axis::LeftY, 1.0),
+            left_arrow: pressed("left_arrow") || stick(Axis::LeftX, -1.0),
+            right_arrow: pressed("right_arrow") || stick(Axis::LeftX, 1.0),
+            menu: pressed("menu"),
+            select: pressed("select"),
+            start: pressed("start"),
+            volume_down: pressed("volume_down"),
+            volume_up: pressed("volume_up"),
+            x: pressed("x"),
+            y: pressed("y"),
+            a: pressed("a"),
+            b: pressed("b"),
+            light_key_1: pressed("light_key_1"),
+            light_key_2: pressed("light_key_2"),
+            light_key_3: pressed("light_key_3"),
+            light_key_4: pressed("light_key_4"),
+            light_key_5: pressed("light_key_5"),
+        }
+    }
+}
+
+/// Returns the name of the logical key in GameKeys with the given name
+fn game_key(name: &str) -> Result<&'static str, ReadBindingsError> {
+    DEFAULT_BINDINGS.iter()
+        .map(|&(game_key, _, _)| game_key)
+        .find(|&game_key| game_key == name)
+        .ok_or_else(|| ReadBindingsError::UnknownGameKey {name: name.to_string()})
+}
+
+/// Every game controller that is currently connected. Controllers are opened as they are plugged
+/// in and closed as they are unplugged.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    /// Each open controller by its joystick instance ID
+    open: HashMap<i32, GameController>,
+}
+
+impl Controllers {
+    /// Opens every game controller that is already connected
+    pub fn new(subsystem: GameControllerSubsystem) -> Result<Self, String> {
+        let mut controllers = Self {
+            subsystem,
+            open: HashMap::new(),
+        };
+        for index in 0..controllers.subsystem.num_joysticks()? {
+            controllers.add(index);
+        }
+        Ok(controllers)
+    }
+
+    /// Opens or closes a controller if the event says that it was plugged in or unplugged
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded {which, ..} => self.add(which),
+            Event::ControllerDeviceRemoved {which, ..} => {
+                self.open.remove(&which);
+            },
+            _ => {},
+        }
+    }
+
+    fn add(&mut self, joystick_index: u32) {
+        // Joysticks that SDL doesn't know how to use as a controller are ignored
+        if !self.subsystem.is_game_controller(joystick_index) {
+            return;
+        }
+
+        // SDL also announces controllers that were already connected when it started, so the same
+        // controller may be added twice. It has the same instance ID both times.
+        match self.subsystem.open(joystick_index) {
+            Ok(controller) => {
+                self.open.insert(controller.instance_id(), controller);
+            },
+            Err(err) => eprintln!("Unable to open game controller {}: {}", joystick_index, err),
+        }
+    }
+
+    /// Returns true if any of the given buttons are pressed on any controller
+    fn any_pressed(&self, buttons: &[Button]) -> bool {
+        self.open.values()
+            .any(|controller| buttons.iter().any(|&button| controller.button(button)))
+    }
+
+    /// Returns true if the given axis is pushed past the dead zone in the given direction (-1.0 or
+    /// 1.0) on any controller
+    fn any_pushed(&self, axis: Axis, direction: f64, dead_zone: f64) -> bool {
+        self.open.values()
+            .any(|controller| controller.axis(axis) as f64 / i16::max_value() as f64 * direction > dead_zone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<InputBindings, ReadBindingsError> {
+        InputBindings::from_reader(json.as_bytes())
+    }
+
+    #[test]
+    fn loads_valid_bindings() {
+        let bindings = parse(r#"{
+            "keys": {"b": ["Left Shift", "Keypad +"]},
+            "buttons": {"up_arrow": ["dpup", "y"]},
+            "stick_dead_zone": 0.25
+        }"#).unwrap();
+
+        assert_eq!(bindings.keys["b"], vec![Scancode::LShift, Scancode::KpPlus]);
+        assert_eq!(bindings.buttons["up_arrow"], vec![Button::DPadUp, Button::Y]);
+        assert_eq!(bindings.stick_dead_zone, 0.25);
+        // Anything left out keeps its default bindings
+        assert_eq!(bindings.keys["up_arrow"], vec![Scancode::Up, Scancode::W]);
+        assert_eq!(bindings.buttons["b"], vec![Button::A]);
+
+        // The bindings shipped with the game are valid too
+        parse(include_str!("../config/bindings.json")).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_game_keys() {
+        match parse(r#"{"keys": {"jump": ["Space"]}}"#) {
+            Err(ReadBindingsError::UnknownGameKey {ref name}) => assert_eq!(name, "jump"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match parse(r#"{"buttons": {"jump": ["a"]}}"#) {
+            Err(ReadBindingsError::UnknownGameKey {ref name}) => assert_eq!(name, "jump"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        match parse(r#"{"keys": {"a": ["K", "Not A Key"]}}"#) {
+            Err(ReadBindingsError::UnknownKey {ref name, ref key}) => {
+                assert_eq!(name, "a");
+                assert_eq!(key, "Not A Key");
+            },
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_buttons() {
+        match parse(r#"{"buttons": {"a": ["b", "notabutton"]}}"#) {
+            Err(ReadBindingsError::UnknownButton {ref name, ref button}) => {
+                assert_eq!(name, "a");
+                assert_eq!(button, "notabutton");
+            },
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_dead_zones_outside_of_range() {
+        for &dead_zone in &[-0.1, 1.0, 1.5] {
+            match parse(&format!(r#"{{"stick_dead_zone": {}}}"#, dead_zone)) {
+                Err(ReadBindingsError::InvalidDeadZone(value)) => assert_eq!(value, dead_zone),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        parse(r#"{"stick_dead_zone": 0.0}"#).unwrap();
+    }
+}
@@ -58,6 +58,10 @@ pub struct Level {
     pub version: u32,
     #[serde(rename = "nextobjectid")]
     pub next_object_id: i32,
+    /// Custom properties set on the whole map in the editor
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, PropertyValue>,
 }
 
 impl Level {
@@ -132,11 +136,54 @@ pub struct Object {
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub polyline: Vec<Coordinate>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub polygon: Vec<Coordinate>,
     pub visible: bool,
+    /// Custom properties set on this object in the editor
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, PropertyValue>,
 }
 
 fn is_false(x: &bool) -> bool { !x }
 
+/// The value of a custom property. Tiled stores the type of each property separately (in
+/// `propertytypes`), but the JSON value alone is enough to tell them apart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl PropertyValue {
+    /// Returns the value as a float if it is a number (int or float)
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            PropertyValue::Int(value) => Some(value as f64),
+            PropertyValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            PropertyValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            PropertyValue::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Coordinate {
     pub x: f64,
@@ -159,6 +206,11 @@ pub struct TileSet {
     #[serde(rename = "tileheight")]
     pub tile_height: u32,
     pub tiles: HashMap<TileId, Tile>,
+    /// Custom properties set on individual tiles in the editor
+    #[serde(default)]
+    #[serde(rename = "tileproperties")]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub tile_properties: HashMap<TileId, HashMap<String, PropertyValue>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
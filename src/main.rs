@@ -15,6 +15,7 @@ extern crate serde_json;
 extern crate sdl2;
 extern crate specs;
 extern crate shred;
+extern crate shrev;
 extern crate nalgebra;
 extern crate ncollide2d;
 extern crate nphysics2d;
@@ -27,11 +28,12 @@ mod resources;
 mod texture_manager;
 mod level_file;
 mod map;
+mod snapshot;
+mod physics_query;
+mod config;
+mod input;
 
-use std::{
-    thread,
-    time::Duration,
-};
+use std::env;
 
 use sdl2::{
     event::Event,
@@ -46,41 +48,78 @@ use specs::{
 
 use components::{
     Position,
+    LockRotation,
     BoundingBox,
     Velocity,
     AppliedAcceleration,
     Density,
+    Kinematic,
+    PathFollower,
+    Trigger,
     Sprite,
     KeyboardControlled,
+    JumpControl,
     CameraFocus,
     MovementAnimation,
     Collisons,
+    CollisionLayer,
 };
-use resources::{FramesElapsed, GameKeys};
+use resources::{GameKeys, PhysicsDebug, Snapshots, SnapshotRequest, Camera};
+use snapshot::ComponentSnapshot;
 use texture_manager::TextureManager;
 use renderer::Renderer;
-use map::LevelMap;
+use map::{LevelMap, CollisionRules};
+use config::{ConfigWatcher, GameConfig, PhysicsConfig, MovementConfig};
+use input::{InputBindings, Controllers};
 use math::Vec2D;
 
 fn main() -> Result<(), String> {
     let fps = 60.0;
+    // The maximum number of frames to simulate before rendering again. Stops the game from
+    // spiraling out of control trying to catch up after a long pause or a slow frame.
+    let max_catch_up_frames = 5;
 
     let mut renderer = Renderer::init(320, 240)?;
     let texture_creator = renderer.texture_creator();
     let mut textures = TextureManager::new(&texture_creator);
     let mut event_pump = renderer.event_pump()?;
+    let mut controllers = Controllers::new(renderer.game_controller()?)?;
 
     let mut world = World::new();
 
-    world.add_resource(FramesElapsed(1));
-    world.add_resource(GameKeys::from(event_pump.keyboard_state()));
+    //FIXME: Remove this unwrap() when we start using proper error types
+    let bindings = InputBindings::load_file("config/bindings.json").unwrap();
+    world.add_resource(bindings.game_keys(&event_pump.keyboard_state(), &controllers));
+    world.add_resource(Snapshots::default());
+    let (screen_width, screen_height) = renderer.dimensions();
+    world.add_resource(Camera {width: screen_width, height: screen_height});
     //FIXME: Remove this unwrap() when we start using proper error types
     let level_map = LevelMap::load_file("maps/level1.json", &mut textures).unwrap();
     world.add_resource(level_map.clone());
 
-    let mut dispatcher = DispatcherBuilder::new()
-        .with(systems::Keyboard::default(), "Keyboard", &[])
-        .with(systems::Physics::new(fps, &level_map), "Physics", &["Keyboard"])
+    // The config is reloaded whenever the file changes so the game can be tuned while it runs
+    let mut config_watcher = ConfigWatcher::new("config/gameplay.json", level_map.properties().clone());
+    //FIXME: Remove this unwrap() when we start using proper error types
+    let config = config_watcher.load().unwrap();
+    world.add_resource(config.physics);
+    world.add_resource(config.movement);
+
+    let dispatcher = DispatcherBuilder::new()
+        .with(systems::Culling, "Culling", &[])
+        .with(systems::Keyboard::new(fps), "Keyboard", &["Culling"])
+        .with(systems::PathFollowing::new(fps), "PathFollowing", &["Culling"]);
+    // The physics backend can be switched with an environment variable for comparison
+    let physics_deps = &["Culling", "Keyboard", "PathFollowing"];
+    let dispatcher = match env::var("PHYSICS").as_ref().map(|backend| backend.as_str()) {
+        Err(_) | Ok("nphysics") => {
+            dispatcher.with(systems::Physics::new(fps, &level_map, config.physics), "Physics", physics_deps)
+        },
+        Ok("character_controller") => {
+            dispatcher.with(systems::CharacterController::new(fps, &level_map), "Physics", physics_deps)
+        },
+        Ok(backend) => panic!("PHYSICS must be nphysics or character_controller, not {}", backend),
+    };
+    let mut dispatcher = dispatcher
         .with(systems::Animator, "Animator", &["Physics"])
         .build();
     dispatcher.setup(&mut world.res);
@@ -96,11 +135,13 @@ fn main() -> Result<(), String> {
         Rect::new(110, 115, 32, 30),
         Rect::new(110, 145, 32, 30),
     ];
-    world.create_entity()
+    let builder = world.create_entity()
         .with(KeyboardControlled)
+        .with(JumpControl::default())
         .with(CameraFocus)
         .with(Position(robot_center))
         .with(Density(1000.0))
+        .with(LockRotation)
         .with(BoundingBox {width: 32, height: 30})
         .with(Collisons::default())
         .with(Velocity(Vec2D::zeros()))
@@ -114,45 +155,143 @@ fn main() -> Result<(), String> {
             steps: robot_animation.into_iter().map(|&rect| (robot_texture, rect)).collect(),
             frames_per_step: 5,
             frame_counter: 0,
-        })
-        .build();
+        });
+    // Levels without a player layer leave the player colliding with everything
+    let builder = if level_map.collision_rules().has_layer(CollisionRules::PLAYER_LAYER) {
+        builder.with(CollisionLayer(CollisionRules::PLAYER_LAYER.to_string()))
+    }
+    else {
+        builder
+    };
+    builder.build();
+
+    // Add the moving platforms
+    for platform in level_map.moving_platforms() {
+        let path: Vec<_> = platform.path.iter().map(|point| point.coords).collect();
+        let builder = world.create_entity()
+            .with(Kinematic)
+            .with(Position(path[0]))
+            .with(BoundingBox {width: platform.width, height: platform.height})
+            .with(Velocity(Vec2D::zeros()))
+            .with(PathFollower::new(path, platform.speed, platform.mode, platform.wait_frames));
+
+        let builder = match platform.texture_id {
+            Some(texture_id) => builder.with(Sprite {
+                texture_id,
+                region: Rect::new(0, 0, platform.width, platform.height),
+                flip_horizontal: false,
+            }),
+            None => builder,
+        };
+        let builder = match platform.collision_layer {
+            Some(ref layer) => builder.with(CollisionLayer(layer.clone())),
+            None => builder,
+        };
+        builder.build();
+    }
+
+    // Add the triggers
+    for trigger in level_map.triggers() {
+        world.create_entity()
+            .with(Trigger {
+                name: trigger.name.clone(),
+                shape: trigger.shape.clone(),
+                properties: trigger.properties.clone(),
+            })
+            .build();
+    }
 
     let mut timer = renderer.timer()?;
 
-    // Frames elapsed since the last render
-    let mut last_frames_elapsed = 0;
+    // Systems are always run with a fixed timestep so that the gameplay doesn't change when the
+    // frame rate does. Any time that is not yet enough for a full frame is accumulated and used
+    // for the next frame.
+    let frame_duration = 1000.0 / fps; // ms
+    let mut accumulator = 0.0; // ms
+    let mut last_ticks = timer.ticks(); // ms
     let mut running = true;
     while running {
-        let ticks = timer.ticks(); // ms
-
         for event in event_pump.poll_iter() {
+            // Controllers can be plugged in and unplugged at any time
+            controllers.handle_event(&event);
+
             match event {
                 Event::Quit {..} | Event::KeyDown {keycode: Some(Keycode::Escape), ..} => {
                     running = false;
                 },
+                // Toggles the physics debug overlay
+                Event::KeyDown {keycode: Some(Keycode::F1), repeat: false, ..} => {
+                    let mut physics_debug = world.write_resource::<PhysicsDebug>();
+                    physics_debug.enabled = !physics_debug.enabled;
+                },
+                Event::KeyDown {keycode: Some(Keycode::F5), repeat: false, ..} => {
+                    world.write_resource::<Snapshots>().request = Some(SnapshotRequest::Save);
+                },
+                Event::KeyDown {keycode: Some(Keycode::F9), repeat: false, ..} => {
+                    world.write_resource::<Snapshots>().request = Some(SnapshotRequest::Restore);
+                },
                 _ => {},
             }
         }
 
-        let frames_elapsed = (ticks as f64 / 1000.0 * fps) as usize;
-        let frames_elapsed_delta = frames_elapsed - last_frames_elapsed;
+        match config_watcher.reload_if_changed() {
+            Some(Ok(config)) => apply_config(&world, config),
+            // Keep using the old config so a mistake in the file doesn't stop the game
+            Some(Err(err)) => eprintln!("Unable to reload config: {}", err),
+            None => {},
+        }
+
+        let ticks = timer.ticks();
+        accumulator += (ticks - last_ticks) as f64;
+        last_ticks = ticks;
 
-        // At least one frame must have passed for us to do anything
-        if frames_elapsed_delta >= 1 {
-            *world.write_resource::<FramesElapsed>() = FramesElapsed(frames_elapsed_delta);
-            *world.write_resource::<GameKeys>() = GameKeys::from(event_pump.keyboard_state());
+        let keys = bindings.game_keys(&event_pump.keyboard_state(), &controllers);
+        let mut frames_run = 0;
+        while accumulator >= frame_duration && frames_run < max_catch_up_frames {
+            *world.write_resource::<GameKeys>() = keys.clone();
 
             dispatcher.dispatch(&mut world.res);
+            // Deletes any entities that were marked for deletion during this frame
+            world.maintain();
 
-            renderer.render(&world, &textures)?;
-            last_frames_elapsed = frames_elapsed;
+            // The physics system has already handled this request during the frame
+            let snapshot_request = world.write_resource::<Snapshots>().request.take();
+            match snapshot_request {
+                Some(SnapshotRequest::Save) => {
+                    let components = ComponentSnapshot::save(&world);
+                    world.write_resource::<Snapshots>().components = Some(components);
+                },
+                Some(SnapshotRequest::Restore) => {
+                    {
+                        let snapshots = world.read_resource::<Snapshots>();
+                        if let Some(ref components) = snapshots.components {
+                            components.restore(&world);
+                        }
+                    }
+                    // Deletes any entities created since the snapshot was taken
+                    world.maintain();
+                },
+                None => {},
+            }
+
+            accumulator -= frame_duration;
+            frames_run += 1;
         }
-        else {
-            let ms_per_frame = (1000.0 / fps) as u64;
-            let ms_elapsed = (timer.ticks() - ticks) as u64;
-            thread::sleep(Duration::from_millis(ms_per_frame - ms_elapsed));
+        // If we still haven't caught up, drop the frames we couldn't run
+        if accumulator >= frame_duration {
+            accumulator %= frame_duration;
         }
+
+        // Rendering is limited by vsync, so this will not render more often than the display can
+        // show. The remaining time is used to interpolate between the last two frames.
+        renderer.render(&world, &textures, accumulator / frame_duration)?;
     }
 
     Ok(())
 }
+
+/// Replaces the config resources with the given config
+fn apply_config(world: &World, config: GameConfig) {
+    *world.write_resource::<PhysicsConfig>() = config.physics;
+    *world.write_resource::<MovementConfig>() = config.movement;
+}
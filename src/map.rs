@@ -1,13 +1,16 @@
 use std::{
     cmp,
     path::Path,
+    collections::{HashMap, HashSet},
 };
 
-use sdl2::rect::{Point, Rect};
+use sdl2::rect::Rect;
 use nalgebra::Point2;
 
 use texture_manager::{TextureManager, TextureId};
-use level_file::{ReadLevelError, Level, Layer, TileId, Object, Coordinate};
+use components::{PathMode, TriggerShape};
+use math::Vec2D;
+use level_file::{ReadLevelError, Level, Layer, TileId, Object, Coordinate, PropertyValue};
 
 /// A grid of tiles. Must have at least one row and one column.
 #[derive(Debug, Clone)]
@@ -51,15 +54,199 @@ pub struct Tile {
     pub image_height: u32,
 }
 
+/// A polyline in the level that rigid bodies collide with. Static boundaries never move.
+#[derive(Debug, Clone)]
+pub struct StaticBoundary {
+    /// The points of the line in world coordinates
+    pub points: Vec<Point2<f64>>,
+    /// The friction of the boundary's surface (`friction` property in the map), if specified
+    pub friction: Option<f64>,
+    /// The restitution of the boundary's surface (`bounciness` property in the map), if specified
+    pub restitution: Option<f64>,
+    /// One-way boundaries (`one_way` property in the map) can be passed through from below and
+    /// stood on from above
+    pub one_way: bool,
+}
+
+/// A platform that moves along a path. Platforms are kinematic bodies, so they move regardless
+/// of anything that collides with them.
+#[derive(Debug, Clone)]
+pub struct MovingPlatform {
+    /// The points that the center of the platform moves between, in world coordinates. The
+    /// platform starts at the first point.
+    pub path: Vec<Point2<f64>>,
+    pub width: u32,
+    pub height: u32,
+    /// Unit: pixels / second
+    pub speed: f64,
+    pub mode: PathMode,
+    /// The number of frames to wait at each point in the path
+    pub wait_frames: usize,
+    /// The image to draw the platform with, if any. The image is expected to be the same size
+    /// as the platform.
+    pub texture_id: Option<TextureId>,
+    /// The collision layer of the platform (`collision_layer` property in the map), if any
+    pub collision_layer: Option<String>,
+}
+
+/// An invisible region of the map that does something when entered
+#[derive(Debug, Clone)]
+pub struct TriggerRegion {
+    pub name: String,
+    pub shape: TriggerShape,
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+/// A rectangular body of water (or any other fluid) that pushes up anything in it and slows it
+/// down
+#[derive(Debug, Clone)]
+pub struct FluidRegion {
+    pub name: String,
+    /// The center of the region in world coordinates
+    pub center: Vec2D,
+    pub width: f64,
+    pub height: f64,
+    /// The density of the fluid (`density` property in the map). A rigid body with a lower Density
+    /// floats on top of the fluid.
+    pub density: f64,
+    /// How quickly the fluid slows down anything moving through it (`drag` property in the map).
+    /// Unit: 1 / second
+    pub drag: f64,
+}
+
+/// How bodies on two collision layers interact with each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerInteraction {
+    /// The bodies push each other apart and are detected by each other's direction sensors
+    Collide,
+    /// The bodies pass through each other, but overlapping is reported
+    Overlap,
+    /// The bodies pass through each other without anything being reported
+    Ignore,
+}
+
+/// The collision layers used in a level and how each pair of layers interacts. Layers collide
+/// with each other unless the map says otherwise.
+///
+/// Configured with properties on the map itself:
+///
+/// * `collision_layers` - comma separated names of every layer (e.g. `player, enemy, pickup`)
+/// * `overlapping_layers` - comma separated pairs of layers that only overlap
+///   (e.g. `player:pickup`)
+/// * `ignored_layers` - comma separated pairs of layers that ignore each other entirely
+///   (e.g. `enemy:enemy, pickup:pickup`)
+#[derive(Debug, Clone, Default)]
+pub struct CollisionRules {
+    layers: Vec<String>,
+    /// Pairs of layer indexes that only overlap, always with the lower index first
+    overlapping: HashSet<(usize, usize)>,
+    /// Pairs of layer indexes that ignore each other, always with the lower index first
+    ignored: HashSet<(usize, usize)>,
+}
+
+impl CollisionRules {
+    /// The most layers that can be used in a single level. Limited by the number of collision
+    /// groups available in the physics engine.
+    pub const MAX_LAYERS: usize = 14;
+    /// The layer that the player is put on if the level has a layer with this name
+    pub const PLAYER_LAYER: &'static str = "player";
+
+    fn from_properties(properties: &HashMap<String, PropertyValue>) -> Self {
+        let layers: Vec<_> = string_property(properties, "collision_layers")
+            .map_or_else(Vec::new, |layers| layers.split(',')
+                .map(|layer| layer.trim().to_string())
+                .filter(|layer| !layer.is_empty())
+                .collect());
+        assert!(layers.len() <= Self::MAX_LAYERS, "A level can have at most {} collision layers", Self::MAX_LAYERS);
+
+        let mut rules = Self {layers, ..Default::default()};
+        rules.overlapping = rules.layer_pairs(properties, "overlapping_layers");
+        rules.ignored = rules.layer_pairs(properties, "ignored_layers");
+        assert!(rules.overlapping.is_disjoint(&rules.ignored),
+            "A pair of collision layers can't both overlap and ignore each other");
+        rules
+    }
+
+    /// Parses a comma separated list of `layer:layer` pairs from the given property
+    fn layer_pairs(&self, properties: &HashMap<String, PropertyValue>, name: &str) -> HashSet<(usize, usize)> {
+        let pairs = match string_property(properties, name) {
+            Some(pairs) => pairs,
+            None => return HashSet::new(),
+        };
+
+        pairs.split(',').filter(|pair| !pair.trim().is_empty()).map(|pair| {
+            let mut layers = pair.split(':').map(|layer| self.layer(layer.trim()));
+            match (layers.next(), layers.next(), layers.next()) {
+                (Some(layer1), Some(layer2), None) => (layer1.min(layer2), layer1.max(layer2)),
+                _ => panic!("Expected a pair of collision layers like `player:pickup` in `{}`, found: {}", name, pair),
+            }
+        }).collect()
+    }
+
+    /// Returns the index of the collision layer with the given name
+    pub fn layer(&self, name: &str) -> usize {
+        self.layers.iter().position(|layer| layer == name)
+            .unwrap_or_else(|| panic!("Unknown collision layer: {}", name))
+    }
+
+    /// Returns true if the level has a collision layer with the given name
+    pub fn has_layer(&self, name: &str) -> bool {
+        self.layers.iter().any(|layer| layer == name)
+    }
+
+    /// Returns the number of collision layers in the level
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Returns how bodies on the two given layers interact
+    pub fn interaction(&self, layer1: usize, layer2: usize) -> LayerInteraction {
+        let pair = (layer1.min(layer2), layer1.max(layer2));
+        if self.overlapping.contains(&pair) {
+            LayerInteraction::Overlap
+        }
+        else if self.ignored.contains(&pair) {
+            LayerInteraction::Ignore
+        }
+        else {
+            LayerInteraction::Collide
+        }
+    }
+}
+
+/// Returns the value of a numeric property of a map object, if that property was set
+fn number_property(properties: &HashMap<String, PropertyValue>, name: &str) -> Option<f64> {
+    properties.get(name).map(|value| value.as_f64()
+        .unwrap_or_else(|| panic!("The `{}` property must be a number", name)))
+}
+
+/// Returns the value of a boolean property of a map object, if that property was set
+fn bool_property(properties: &HashMap<String, PropertyValue>, name: &str) -> Option<bool> {
+    properties.get(name).map(|value| value.as_bool()
+        .unwrap_or_else(|| panic!("The `{}` property must be a boolean", name)))
+}
+
+/// Returns the value of a string property of a map object, if that property was set
+fn string_property<'a>(properties: &'a HashMap<String, PropertyValue>, name: &str) -> Option<&'a str> {
+    properties.get(name).map(|value| value.as_str()
+        .unwrap_or_else(|| panic!("The `{}` property must be a string", name)))
+}
+
 /// Resource that represents a map of tiles for an entire level of the game.
 ///
 /// Top-left of the top-left tile is at position (0, 0). Each tile is of constant width and height,
 /// so computing tiles within a boundary rectangle is fairly trivial.
 #[derive(Debug, Clone)]
 pub struct LevelMap {
-    level_start: Point,
+    level_start: Vec2D,
     level_boundary: Rect,
-    static_boundaries: Vec<Vec<Point2<f64>>>,
+    static_boundaries: Vec<StaticBoundary>,
+    moving_platforms: Vec<MovingPlatform>,
+    triggers: Vec<TriggerRegion>,
+    fluids: Vec<FluidRegion>,
+    collision_rules: CollisionRules,
+    /// The custom properties of the map itself
+    properties: HashMap<String, PropertyValue>,
     rows: usize,
     columns: usize,
     tile_width: usize,
@@ -88,6 +275,7 @@ impl LevelMap {
             tile_height,
             ref tile_sets,
             ref layers,
+            ref properties,
             ..
         } = level;
 
@@ -100,7 +288,7 @@ impl LevelMap {
                 let tile_id = tile_id - id_offset;
                 let key = &TileId(tile_id);
                 if tile_set.tiles.contains_key(key) {
-                    return Some(&tile_set.tiles[key]);
+                    return Some((&tile_set.tiles[key], tile_set.tile_properties.get(key)));
                 }
             }
 
@@ -113,6 +301,10 @@ impl LevelMap {
         let mut level_start = None;
         let mut level_boundary = None;
         let mut static_boundaries = Vec::new();
+        let mut moving_platforms = Vec::new();
+        let mut triggers = Vec::new();
+        let mut fluids = Vec::new();
+        let collision_rules = CollisionRules::from_properties(properties);
 
         for layer in layers {
             match layer {
@@ -121,6 +313,9 @@ impl LevelMap {
                     assert_eq!(rows, *layer_height);
 
                     let mut tile_rows = Vec::with_capacity(rows as usize);
+                    // The left and right x coordinates of each horizontal run of one-way tiles in
+                    // each row
+                    let mut one_way_runs: Vec<Vec<(i32, i32)>> = Vec::with_capacity(rows as usize);
 
                     let mut row = -1;
                     let mut col = 0;
@@ -129,8 +324,28 @@ impl LevelMap {
                             row += 1;
                             col = 0;
                             tile_rows.push(Vec::with_capacity(columns as usize));
+                            one_way_runs.push(Vec::new());
+                        }
+
+                        let tile_left = col * tile_width as i32;
+                        let tile_right = tile_left + tile_width as i32;
+                        let one_way = lookup_tile(id)
+                            .and_then(|(_, properties)| properties)
+                            .and_then(|properties| bool_property(properties, "one_way"))
+                            .unwrap_or(false);
+                        if one_way {
+                            let runs = one_way_runs.last_mut().unwrap();
+                            // Extend the previous run if this tile is right next to it
+                            let extends_run = runs.last().map_or(false, |&(_, right)| right == tile_left);
+                            if extends_run {
+                                runs.last_mut().unwrap().1 = tile_right;
+                            }
+                            else {
+                                runs.push((tile_left, tile_right));
+                            }
                         }
-                        let tile = lookup_tile(id).map(|tile| {
+
+                        let tile = lookup_tile(id).map(|(tile, _)| {
                             //FIXME: Remove this unwrap() when we start using proper error types
                             let image_path = resolve_dir.join(&tile.image).canonicalize().unwrap();
                             //FIXME: Remove this unwrap() when we start using proper error types
@@ -150,6 +365,21 @@ impl LevelMap {
                     assert_eq!(tile_rows.len(), rows as usize);
                     assert!(tile_rows.iter().all(|r| r.len() == columns as usize));
 
+                    // One-way tiles only make sense for the tiles that things can stand on
+                    if name == "map" {
+                        for (row, runs) in one_way_runs.into_iter().enumerate() {
+                            let y = (row as u32 * tile_height) as f64;
+                            for (left, right) in runs {
+                                static_boundaries.push(StaticBoundary {
+                                    points: vec![Point2::new(left as f64, y), Point2::new(right as f64, y)],
+                                    friction: None,
+                                    restitution: None,
+                                    one_way: true,
+                                });
+                            }
+                        }
+                    }
+
                     let tile_grid = TileGrid(tile_rows);
                     match name.as_str() {
                         "background" => background = Some(tile_grid),
@@ -161,7 +391,7 @@ impl LevelMap {
                 Layer::ObjectGroup {name, objects, ..} => {
                     assert_eq!(name, "markers");
 
-                    for &Object {ref type_, x, y, width, height, rotation, point, ref polyline, ..} in objects {
+                    for &Object {ref type_, ref name, x, y, width, height, rotation, point, ref polyline, ref polygon, ref properties, ..} in objects {
                         match type_.as_str() {
                             "level_start" => {
                                 assert!(point);
@@ -170,7 +400,7 @@ impl LevelMap {
                                 // Point should not have any size information
                                 assert!(width < ::std::f64::EPSILON);
                                 assert!(height < ::std::f64::EPSILON);
-                                level_start = Some(Point::new(x as i32, y as i32));
+                                level_start = Some(Vec2D::new(x, y));
                             },
                             "level_boundary" => {
                                 assert!(!point);
@@ -190,10 +420,105 @@ impl LevelMap {
                                 assert!(rotation < ::std::f64::EPSILON);
 
                                 let offset = Point2::new(x, y);
-                                let boundary_line = polyline.iter()
+                                let points = polyline.iter()
+                                    .map(|&Coordinate {x, y}| Point2::new(x + offset.x, y + offset.y))
+                                    .collect();
+                                static_boundaries.push(StaticBoundary {
+                                    points,
+                                    friction: number_property(properties, "friction"),
+                                    restitution: number_property(properties, "bounciness"),
+                                    one_way: bool_property(properties, "one_way").unwrap_or(false),
+                                });
+                            },
+                            "moving_platform" => {
+                                assert!(!point);
+                                // Must not be rotated
+                                assert!(rotation < ::std::f64::EPSILON);
+
+                                let offset = Point2::new(x, y);
+                                let path: Vec<_> = polyline.iter()
                                     .map(|&Coordinate {x, y}| Point2::new(x + offset.x, y + offset.y))
                                     .collect();
-                                static_boundaries.push(boundary_line);
+                                assert!(path.len() >= 2, "Moving platform path must have at least two points");
+
+                                let mode = match string_property(properties, "mode") {
+                                    None | Some("ping_pong") => PathMode::PingPong,
+                                    Some("loop") => PathMode::Loop,
+                                    Some(mode) => unreachable!("Unrecognized moving platform mode: {}", mode),
+                                };
+
+                                let texture_id = string_property(properties, "image").map(|image| {
+                                    //FIXME: Remove this unwrap() when we start using proper error types
+                                    let image_path = resolve_dir.join(image).canonicalize().unwrap();
+                                    //FIXME: Remove this unwrap() when we start using proper error types
+                                    texture_manager.create_png_texture(image_path).unwrap()
+                                });
+
+                                let collision_layer = string_property(properties, "collision_layer").map(|layer| {
+                                    // Checked here so that unknown layers are found when the level loads
+                                    collision_rules.layer(layer);
+                                    layer.to_string()
+                                });
+
+                                moving_platforms.push(MovingPlatform {
+                                    path,
+                                    width: number_property(properties, "width")
+                                        .expect("Moving platform must have a width property") as u32,
+                                    height: number_property(properties, "height")
+                                        .expect("Moving platform must have a height property") as u32,
+                                    speed: number_property(properties, "speed")
+                                        .expect("Moving platform must have a speed property"),
+                                    mode,
+                                    wait_frames: number_property(properties, "wait_frames").unwrap_or(0.0) as usize,
+                                    texture_id,
+                                    collision_layer,
+                                });
+                            },
+                            "trigger" => {
+                                assert!(!point);
+                                // Must not be rotated
+                                assert!(rotation < ::std::f64::EPSILON);
+
+                                let shape = if polygon.is_empty() {
+                                    assert!(width > 0.0 && height > 0.0, "Trigger must have a size");
+                                    TriggerShape::Rect {
+                                        center: Vec2D::new(x + width / 2.0, y + height / 2.0),
+                                        width,
+                                        height,
+                                    }
+                                }
+                                else {
+                                    TriggerShape::Polygon(polygon.iter()
+                                        .map(|&Coordinate {x: px, y: py}| Point2::new(x + px, y + py))
+                                        .collect())
+                                };
+
+                                triggers.push(TriggerRegion {
+                                    name: name.clone(),
+                                    shape,
+                                    properties: properties.clone(),
+                                });
+                            },
+                            "fluid" => {
+                                assert!(!point);
+                                // Must not be rotated
+                                assert!(rotation < ::std::f64::EPSILON);
+                                assert!(width > 0.0 && height > 0.0, "Fluid must have a size");
+
+                                let density = number_property(properties, "density")
+                                    .expect("Fluid must have a density property");
+                                assert!(density > 0.0, "Fluid density must be positive");
+                                let drag = number_property(properties, "drag").unwrap_or(0.0);
+                                assert!(drag >= 0.0, "Fluid drag must not be negative");
+
+                                fluids.push(FluidRegion {
+                                    name: name.clone(),
+                                    center: Vec2D::new(x + width / 2.0, y + height / 2.0),
+                                    width,
+                                    height,
+                                    density,
+                                    drag,
+                                });
                             },
                             _ => unreachable!("Unrecognized object type in markers layer: {}", type_),
                         }
@@ -206,6 +531,11 @@ impl LevelMap {
             level_start: level_start.unwrap(),
             level_boundary: level_boundary.unwrap(),
             static_boundaries,
+            moving_platforms,
+            triggers,
+            fluids,
+            collision_rules,
+            properties: properties.clone(),
             rows: rows as usize,
             columns: columns as usize,
             tile_width: tile_width as usize,
@@ -216,7 +546,7 @@ impl LevelMap {
         })
     }
 
-    pub fn level_start(&self) -> Point {
+    pub fn level_start(&self) -> Vec2D {
         self.level_start
     }
 
@@ -224,10 +554,35 @@ impl LevelMap {
         self.level_boundary
     }
 
-    pub fn static_boundaries(&self) -> &[Vec<Point2<f64>>] {
+    /// Returns (width, height) of every tile in the map
+    pub fn tile_size(&self) -> (usize, usize) {
+        (self.tile_width, self.tile_height)
+    }
+
+    pub fn static_boundaries(&self) -> &[StaticBoundary] {
         &self.static_boundaries
     }
 
+    pub fn moving_platforms(&self) -> &[MovingPlatform] {
+        &self.moving_platforms
+    }
+
+    pub fn triggers(&self) -> &[TriggerRegion] {
+        &self.triggers
+    }
+
+    pub fn fluids(&self) -> &[FluidRegion] {
+        &self.fluids
+    }
+
+    pub fn collision_rules(&self) -> &CollisionRules {
+        &self.collision_rules
+    }
+
+    pub fn properties(&self) -> &HashMap<String, PropertyValue> {
+        &self.properties
+    }
+
     pub fn background_within(&self, bounds: Rect) -> impl Iterator<Item=&Tile> {
         self.background.slice_within(self.tile_width, self.tile_height, bounds)
     }
@@ -0,0 +1,174 @@
+//! Read-only queries against the colliders in the physics engine for use by other systems
+
+use std::cmp::Ordering;
+
+use specs::Entity;
+use nalgebra::{Isometry2, Point2};
+use ncollide2d::{
+    bounding_volume::{AABB, BoundingVolume},
+    query::{self, Ray},
+    shape::{Cuboid, ShapeHandle},
+};
+
+use math::Vec2D;
+
+/// A single collider that can be found by a query
+#[derive(Clone)]
+pub struct QueryCollider {
+    pub shape: ShapeHandle<f64>,
+    /// The position of the collider in world coordinates
+    pub position: Isometry2<f64>,
+    /// The entity that the collider belongs to, or None for the static geometry of the map
+    pub entity: Option<Entity>,
+    /// True if this is a one-way boundary
+    pub one_way: bool,
+}
+
+/// Decides which colliders a query can find
+#[derive(Debug, Clone, Default)]
+pub struct QueryFilter {
+    /// Entities that are never found (e.g. the entity doing the query)
+    pub exclude: Vec<Entity>,
+    /// Whether one-way boundaries can be found. Usually false since they only block things from
+    /// above.
+    pub one_way: bool,
+}
+
+impl QueryFilter {
+    /// Returns a filter that finds everything except the given entity and one-way boundaries
+    pub fn excluding(entity: Entity) -> Self {
+        Self {
+            exclude: vec![entity],
+            one_way: false,
+        }
+    }
+
+    fn allows(&self, collider: &QueryCollider) -> bool {
+        if collider.one_way && !self.one_way {
+            return false;
+        }
+        match collider.entity {
+            Some(entity) => !self.exclude.contains(&entity),
+            None => true,
+        }
+    }
+}
+
+/// Something found by a ray cast
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    /// The entity that was hit, or None for the static geometry of the map
+    pub entity: Option<Entity>,
+    /// The point where the ray hit, in world coordinates
+    pub point: Vec2D,
+    /// The normal of the surface that was hit
+    pub normal: Vec2D,
+    /// The distance along the ray to the point that was hit (pixels)
+    pub distance: f64,
+}
+
+/// Something found by a box cast
+#[derive(Debug, Clone, Copy)]
+pub struct BoxHit {
+    /// The entity that was hit, or None for the static geometry of the map
+    pub entity: Option<Entity>,
+    /// How far the box could move before hitting, as a fraction (0.0 to 1.0) of the displacement
+    pub fraction: f64,
+}
+
+/// Resource that lets any system ask what is in the physics engine without having access to it.
+/// Filled in by the physics system (either Physics or CharacterController) at the end of every
+/// frame, so queries see where everything was at the end of the last frame.
+///
+/// Sensors and triggers are never found. Static geometry of the map is found with no entity.
+#[derive(Clone, Default)]
+pub struct PhysicsQuery {
+    colliders: Vec<QueryCollider>,
+}
+
+impl PhysicsQuery {
+    /// Replaces every collider that can be found with the given colliders
+    pub fn update(&mut self, colliders: Vec<QueryCollider>) {
+        self.colliders = colliders;
+    }
+
+    /// Returns the closest thing hit by a ray starting at the given point and going in the given
+    /// direction for at most the given distance
+    pub fn cast_ray(&self, origin: Vec2D, dir: Vec2D, max_distance: f64, filter: &QueryFilter) -> Option<RayHit> {
+        self.cast_ray_all(origin, dir, max_distance, filter).into_iter().next()
+    }
+
+    /// Returns everything hit by a ray starting at the given point and going in the given
+    /// direction for at most the given distance, sorted from closest to furthest
+    pub fn cast_ray_all(&self, origin: Vec2D, dir: Vec2D, max_distance: f64, filter: &QueryFilter) -> Vec<RayHit> {
+        let length = dir.norm();
+        assert!(length > 0.0, "Ray direction must not be zero");
+        let ray = Ray::new(Point2::from_coordinates(origin), dir / length);
+
+        let mut hits: Vec<_> = self.colliders.iter()
+            .filter(|collider| filter.allows(collider))
+            .filter_map(|collider| {
+                let intersection = collider.shape.as_ray_cast()?
+                    .toi_and_normal_with_ray(&collider.position, &ray, true)?;
+                if intersection.toi > max_distance {
+                    return None;
+                }
+
+                Some(RayHit {
+                    entity: collider.entity,
+                    point: origin + ray.dir * intersection.toi,
+                    normal: intersection.normal,
+                    distance: intersection.toi,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
+        hits
+    }
+
+    /// Returns the first thing hit by a box with the given center and half extents as it moves by
+    /// the given displacement. Anything the box already overlaps is hit immediately.
+    pub fn cast_box(&self, center: Vec2D, half_extents: Vec2D, displacement: Vec2D, filter: &QueryFilter) -> Option<BoxHit> {
+        let cuboid = Cuboid::new(half_extents);
+        let position = Isometry2::new(center, 0.0);
+
+        self.colliders.iter()
+            .filter(|collider| filter.allows(collider))
+            .filter_map(|collider| {
+                let fraction = query::time_of_impact(
+                    &position,
+                    &displacement,
+                    &cuboid,
+                    &collider.position,
+                    &Vec2D::zeros(),
+                    &*collider.shape,
+                )?;
+                if fraction > 1.0 {
+                    return None;
+                }
+                Some(BoxHit {entity: collider.entity, fraction})
+            })
+            .min_by(|a, b| a.fraction.partial_cmp(&b.fraction).unwrap_or(Ordering::Equal))
+    }
+
+    /// Returns everything whose bounding box overlaps the given box (in world coordinates)
+    pub fn overlap_aabb(&self, mins: Vec2D, maxs: Vec2D, filter: &QueryFilter) -> Vec<Option<Entity>> {
+        let aabb = AABB::new(Point2::from_coordinates(mins), Point2::from_coordinates(maxs));
+        self.colliders.iter()
+            .filter(|collider| filter.allows(collider))
+            .filter(|collider| collider.shape.aabb(&collider.position).intersects(&aabb))
+            .map(|collider| collider.entity)
+            .collect()
+    }
+
+    /// Returns everything that contains the given point (in world coordinates)
+    pub fn at_point(&self, point: Vec2D, filter: &QueryFilter) -> Vec<Option<Entity>> {
+        let point = Point2::from_coordinates(point);
+        self.colliders.iter()
+            .filter(|collider| filter.allows(collider))
+            .filter(|collider| collider.shape.as_point_query()
+                .map_or(false, |shape| shape.contains_point(&collider.position, &point)))
+            .map(|collider| collider.entity)
+            .collect()
+    }
+}
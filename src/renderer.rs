@@ -1,10 +1,10 @@
 use std::env;
-use std::cmp;
 
 use sdl2::{
     self,
     Sdl,
     TimerSubsystem,
+    GameControllerSubsystem,
     EventPump,
     image::{Sdl2ImageContext, INIT_PNG},
     pixels::Color,
@@ -18,19 +18,33 @@ use specs::{
     World,
     Resources,
     SystemData,
+    Read,
     ReadExpect,
+    Entities,
+    Entity,
 };
 
+use nalgebra::Point2;
+
 use texture_manager::TextureManager;
-use components::{Position, Sprite, CameraFocus};
+use components::{Position, PreviousPosition, Rotation, Sprite, CameraFocus};
+use resources::{PhysicsDebug, DebugOutline, Camera};
 use map::{LevelMap, Tile};
 
+/// How long (in seconds) of movement each velocity vector shows in the physics debug overlay
+const DEBUG_VELOCITY_SCALE: f64 = 0.25;
+
 #[derive(SystemData)]
 struct RenderData<'a> {
+    entities: Entities<'a>,
     map: ReadExpect<'a, LevelMap>,
     camera_focuses: ReadStorage<'a, CameraFocus>,
     positions: ReadStorage<'a, Position>,
+    previous_positions: ReadStorage<'a, PreviousPosition>,
+    rotations: ReadStorage<'a, Rotation>,
     sprites: ReadStorage<'a, Sprite>,
+    physics_debug: Read<'a, PhysicsDebug>,
+    camera: Read<'a, Camera>,
 }
 
 pub struct Renderer {
@@ -99,52 +113,66 @@ impl Renderer {
         self.sdl_context.event_pump()
     }
 
-    pub fn render(&mut self, world: &World, textures: &TextureManager) -> Result<(), String> {
+    pub fn game_controller(&self) -> Result<GameControllerSubsystem, String> {
+        self.sdl_context.game_controller()
+    }
+
+    /// Renders the world. Since rendering may happen in between frames, `alpha` (between 0.0 and
+    /// 1.0) is used to interpolate how far each entity has moved from its previous position
+    /// towards its current position.
+    pub fn render(&mut self, world: &World, textures: &TextureManager, alpha: f64) -> Result<(), String> {
         self.canvas.clear();
 
-        let RenderData {map, positions, sprites, camera_focuses} = world.system_data();
-        let mut camera_focuses = (&positions, &camera_focuses).join();
-        let (&Position(camera_focus), _) = camera_focuses.next().expect("Renderer was not told which entity to focus on");
+        let RenderData {
+            entities,
+            map,
+            positions,
+            previous_positions,
+            rotations,
+            sprites,
+            camera_focuses,
+            physics_debug,
+            camera,
+        } = world.system_data();
+        // Positions are rounded to the nearest pixel only once they have been interpolated
+        let interpolate = |entity: Entity, &Position(pos): &Position| {
+            let pos = match previous_positions.get(entity) {
+                Some(&PreviousPosition(prev)) => prev + (pos - prev) * alpha,
+                None => pos,
+            };
+            Point::new(pos.x.round() as i32, pos.y.round() as i32)
+        };
+
+        let mut camera_focuses = (&*entities, &positions, &camera_focuses).join();
+        let (focus_entity, camera_focus, _) = camera_focuses.next().expect("Renderer was not told which entity to focus on");
         assert!(camera_focuses.next().is_none(),
             "Renderer was asked to focus on more than one thing");
+        let camera_focus = interpolate(focus_entity, camera_focus);
 
-        let (screen_width, screen_height) = self.dimensions();
-        let screen_center = Point::new(screen_width as i32 / 2, screen_height as i32 / 2);
-
+        // Follows the interpolated position of the camera focus so that scrolling is smooth. The
+        // simulation culls using the view around its actual position instead.
+        let screen = camera.view(camera_focus, map.level_boundary());
         // The position on the map of the screen's top left corner
-        // Adding this point to the position of the camera_focus would make it render in the center
-        // of the screen
-        let render_center = camera_focus - screen_center;
-
-        // Need to make sure the camera stays within the level boundary
-        let level_boundary = map.level_boundary();
-        // The valid ranges for the top-left corner of the screen
-        let (min_x, max_x) = (0, level_boundary.x() + level_boundary.width() as i32 - screen_width as i32);
-        let (min_y, max_y) = (0, level_boundary.y() + level_boundary.height() as i32 - screen_height as i32);
-        let clamp = |min, x, max| cmp::min(cmp::max(min, x), max);
-        let render_center = Point::new(
-            clamp(min_x, render_center.x, max_x),
-            clamp(min_y, render_center.y, max_y),
-        );
-
-        // Get the tiles surrounding the camera focus
-        let screen = Rect::from_center(render_center + screen_center, screen_width, screen_height);
+        let render_center = screen.top_left();
 
         self.render_tiles(map.background_within(screen), render_center, textures)?;
         self.render_tiles(map.background_items_within(screen), render_center, textures)?;
 
-        for (&Position(pos), ref sprite) in (&positions, &sprites).join() {
-            let pos = pos - render_center;
+        for (entity, pos, ref sprite) in (&*entities, &positions, &sprites).join() {
+            let pos = interpolate(entity, pos) - render_center;
             let texture = textures.get(sprite.texture_id);
             let source_rect = sprite.region;
             let mut dest_rect = source_rect.clone();
             dest_rect.center_on(pos);
+            // SDL expects degrees clockwise, which is the same direction as a positive Rotation
+            // since +y is downwards. Passing no center rotates the sprite around its center.
+            let angle = rotations.get(entity).map_or(0.0, |&Rotation(angle)| angle.to_degrees());
 
             self.canvas.copy_ex(
                 texture,
                 Some(source_rect),
                 Some(dest_rect),
-                0.0,
+                angle,
                 None,
                 sprite.flip_horizontal,
                 false
@@ -153,11 +181,71 @@ impl Renderer {
 
         self.render_tiles(map.map_within(screen), render_center, textures)?;
 
+        if physics_debug.enabled {
+            self.render_physics_debug(&physics_debug, render_center)?;
+        }
+
         self.canvas.present();
 
         Ok(())
     }
 
+    /// Draws the outlines of everything in the physics engine on top of the game
+    fn render_physics_debug(&mut self, debug: &PhysicsDebug, render_center: Point) -> Result<(), String> {
+        // Converts from world coordinates to camera coordinates
+        let to_screen = |point: Point2<f64>| Point::new(
+            point.x.round() as i32 - render_center.x(),
+            point.y.round() as i32 - render_center.y(),
+        );
+        let outline_points = |outline: &DebugOutline| {
+            let mut points: Vec<_> = outline.points.iter().map(|&point| to_screen(point)).collect();
+            if outline.closed {
+                if let Some(&first) = points.first() {
+                    points.push(first);
+                }
+            }
+            points
+        };
+
+        self.canvas.set_draw_color(Color::RGBA(0, 255, 0, 255));
+        for outline in &debug.colliders {
+            self.canvas.draw_lines(&outline_points(outline)[..])?;
+        }
+
+        self.canvas.set_draw_color(Color::RGBA(0, 128, 255, 255));
+        for outline in &debug.triggers {
+            self.canvas.draw_lines(&outline_points(outline)[..])?;
+        }
+
+        self.canvas.set_draw_color(Color::RGBA(0, 255, 255, 255));
+        for outline in &debug.fluids {
+            self.canvas.draw_lines(&outline_points(outline)[..])?;
+        }
+
+        for &(ref outline, touching) in &debug.sensors {
+            // Red when touching something, white otherwise
+            let color = if touching { Color::RGBA(255, 0, 0, 255) } else { Color::RGBA(255, 255, 255, 255) };
+            self.canvas.set_draw_color(color);
+            self.canvas.draw_lines(&outline_points(outline)[..])?;
+        }
+
+        self.canvas.set_draw_color(Color::RGBA(255, 0, 255, 255));
+        for &(center, velocity) in &debug.velocities {
+            let end = center + velocity * DEBUG_VELOCITY_SCALE;
+            self.canvas.draw_line(to_screen(center), to_screen(end))?;
+        }
+
+        self.canvas.set_draw_color(Color::RGBA(255, 255, 0, 255));
+        for &contact in &debug.contacts {
+            self.canvas.draw_rect(Rect::from_center(to_screen(contact), 3, 3))?;
+        }
+
+        // Reset back to the background color
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
+
+        Ok(())
+    }
+
     fn render_tiles<'a, I: Iterator<Item=&'a Tile>>(&mut self, tiles: I, render_center: Point, textures: &TextureManager) -> Result<(), String> {
         for &Tile {x, y, texture_id, image_width, image_height} in tiles {
             let texture = textures.get(texture_id);
@@ -1,16 +1,185 @@
 //! ECS Resources for use by various systems
 
-use sdl2::keyboard::{KeyboardState, Scancode};
+use std::cmp;
 
-/// Resource that represents the number of frames elapsed since the last time all of the systems
-/// were run. Value is guaranteed to be greater than or equal to 1.
-/// Often this will be just 1 but it may be greater if there is lag or if a system takes too long.
-pub struct FramesElapsed(pub usize);
+use sdl2::rect::{Point, Rect};
+use specs::Entity;
+use nalgebra::Point2;
 
-/// Resource that represents which keys are currently pressed.
+use math::Vec2D;
+use snapshot::ComponentSnapshot;
+use systems::PhysicsSnapshot;
+
+/// Published by the physics system (through an `EventChannel<CollisionEvent>` resource) whenever
+/// two things start or stop touching.
 ///
-/// Each boolean is true if the key is pressed and false otherwise
+/// entity1 is always an entity. entity2 is None when entity1 touched the static geometry of the map.
+#[derive(Debug, Clone, Copy)]
+pub enum CollisionEvent {
+    Started {
+        entity1: Entity,
+        entity2: Option<Entity>,
+        /// The normal of the contact, pointing from entity1 towards entity2
+        normal: Vec2D,
+        /// An estimate of the impulse along the normal that was needed to stop the two from moving
+        /// into each other. Larger for harder hits.
+        impulse: f64,
+    },
+    Stopped {
+        entity1: Entity,
+        entity2: Option<Entity>,
+    },
+}
+
+/// Published by the physics system (through an `EventChannel<OverlapEvent>` resource) whenever
+/// two entities on collision layers that only overlap (see CollisionRules) start or stop
+/// overlapping. Published once for each of the two entities, with that entity as `entity`.
+#[derive(Debug, Clone, Copy)]
+pub enum OverlapEvent {
+    Started {
+        entity: Entity,
+        other: Entity,
+    },
+    Stopped {
+        entity: Entity,
+        other: Entity,
+    },
+}
+
+/// Published by the physics system (through an `EventChannel<ProjectileHit>` resource) whenever
+/// a projectile hits something
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectileHit {
+    pub projectile: Entity,
+    /// The entity that fired the projectile, if any
+    pub owner: Option<Entity>,
+    /// The entity that was hit, or None if the projectile hit the static geometry of the map
+    pub target: Option<Entity>,
+    /// Where the projectile hit, in world coordinates
+    pub point: Vec2D,
+    /// The normal of the surface that was hit
+    pub normal: Vec2D,
+    pub damage: f64,
+}
+
+/// Published by the physics system (through an `EventChannel<TriggerEvent>` resource) for every
+/// entity that is in a Trigger.
+#[derive(Debug, Clone, Copy)]
+pub enum TriggerEvent {
+    /// The entity entered the trigger during the last frame
+    Enter {
+        trigger: Entity,
+        entity: Entity,
+    },
+    /// The entity entered the trigger during a previous frame and is still in it
+    Stay {
+        trigger: Entity,
+        entity: Entity,
+    },
+    /// The entity left the trigger during the last frame
+    Exit {
+        trigger: Entity,
+        entity: Entity,
+    },
+}
+
+/// The outline of a shape in the physics engine in world coordinates
 #[derive(Debug, Clone)]
+pub struct DebugOutline {
+    pub points: Vec<Point2<f64>>,
+    /// True if the last point connects back to the first point
+    pub closed: bool,
+}
+
+/// Published by the physics system (through an `EventChannel<FluidEvent>` resource) whenever a
+/// rigid body enters or leaves a fluid. Fluids are identified by their index in
+/// `LevelMap::fluids()`.
+#[derive(Debug, Clone, Copy)]
+pub enum FluidEvent {
+    /// Part of the entity went into the fluid during the last frame
+    Entered {
+        fluid: usize,
+        entity: Entity,
+    },
+    /// The entity came all the way out of the fluid during the last frame
+    Left {
+        fluid: usize,
+        entity: Entity,
+    },
+}
+
+/// The size of the part of the level that is shown on the screen. The camera is centered on the
+/// entity with the CameraFocus component without ever going outside of the level boundary.
+#[derive(Debug, Clone, Default)]
+pub struct Camera {
+    /// The width of the screen (pixels)
+    pub width: u32,
+    /// The height of the screen (pixels)
+    pub height: u32,
+}
+
+impl Camera {
+    /// Returns the visible region of the level when the camera is focused on the given point
+    pub fn view(&self, focus: Point, level_boundary: Rect) -> Rect {
+        // The valid ranges for the top-left corner of the screen
+        let (min_x, max_x) = (0, level_boundary.x() + level_boundary.width() as i32 - self.width as i32);
+        let (min_y, max_y) = (0, level_boundary.y() + level_boundary.height() as i32 - self.height as i32);
+        let clamp = |min, x, max| cmp::min(cmp::max(min, x), max);
+
+        let top_left = focus - Point::new(self.width as i32 / 2, self.height as i32 / 2);
+        Rect::new(
+            clamp(min_x, top_left.x, max_x),
+            clamp(min_y, top_left.y, max_y),
+            self.width,
+            self.height,
+        )
+    }
+}
+
+/// Filled in by the physics system at the end of every frame while `enabled` is true so that the
+/// renderer can draw what the physics engine is doing on top of the game
+#[derive(Debug, Default)]
+pub struct PhysicsDebug {
+    pub enabled: bool,
+    /// Every collider that things can collide with
+    pub colliders: Vec<DebugOutline>,
+    /// Every direction sensor and whether the Collisons component of its entity says that the
+    /// sensor is touching something
+    pub sensors: Vec<(DebugOutline, bool)>,
+    /// Every trigger region
+    pub triggers: Vec<DebugOutline>,
+    /// Every fluid region
+    pub fluids: Vec<DebugOutline>,
+    /// The center and velocity (pixels / second) of every rigid body
+    pub velocities: Vec<(Point2<f64>, Vec2D)>,
+    /// Every point where two colliders are touching
+    pub contacts: Vec<Point2<f64>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotRequest {
+    /// Save the current state of the simulation, replacing any previously saved state
+    Save,
+    /// Go back to the last saved state of the simulation (if any)
+    Restore,
+}
+
+/// The saved state of the simulation. Set `request` to save or restore the simulation at the end
+/// of the next frame.
+#[derive(Debug, Default)]
+pub struct Snapshots {
+    pub request: Option<SnapshotRequest>,
+    /// Saved by the physics system
+    pub physics: Option<PhysicsSnapshot>,
+    /// Saved after the frame has finished running
+    pub components: Option<ComponentSnapshot>,
+}
+
+/// Resource that represents which keys are currently pressed. The keys on the keyboard that set
+/// each of these are configured with InputBindings.
+///
+/// Each boolean is true if the key is pressed and false otherwise
+#[derive(Debug, Clone, Default)]
 pub struct GameKeys {
     pub up_arrow: bool,
     pub down_arrow: bool,
@@ -31,29 +200,3 @@ pub struct GameKeys {
     pub light_key_4: bool,
     pub light_key_5: bool,
 }
-
-impl<'a> From<KeyboardState<'a>> for GameKeys {
-    fn from(keys: KeyboardState) -> Self {
-        // From mapping: https://github.com/clockworkpi/Keypad#keymaps
-        Self {
-            up_arrow: keys.is_scancode_pressed(Scancode::Up),
-            down_arrow: keys.is_scancode_pressed(Scancode::Down),
-            left_arrow: keys.is_scancode_pressed(Scancode::Left),
-            right_arrow: keys.is_scancode_pressed(Scancode::Right),
-            menu: keys.is_scancode_pressed(Scancode::Escape),
-            select: keys.is_scancode_pressed(Scancode::Space),
-            start: keys.is_scancode_pressed(Scancode::Return),
-            volume_down: keys.is_scancode_pressed(Scancode::KpMinus),
-            volume_up: keys.is_scancode_pressed(Scancode::KpPlus),
-            x: keys.is_scancode_pressed(Scancode::I),
-            y: keys.is_scancode_pressed(Scancode::U),
-            a: keys.is_scancode_pressed(Scancode::K),
-            b: keys.is_scancode_pressed(Scancode::J),
-            light_key_1: keys.is_scancode_pressed(Scancode::H),
-            light_key_2: keys.is_scancode_pressed(Scancode::Y),
-            light_key_3: false, //FIXME: No way to check if Shift key pressed
-            light_key_4: keys.is_scancode_pressed(Scancode::O),
-            light_key_5: keys.is_scancode_pressed(Scancode::L),
-        }
-    }
-}
@@ -0,0 +1,133 @@
+//! Saving and restoring the components that make up the state of the simulation
+
+use specs::{Component, Entity, Join, World};
+
+use components::{
+    Position,
+    PreviousPosition,
+    Rotation,
+    BoundingBox,
+    Density,
+    CollisionLayer,
+    Velocity,
+    AppliedAcceleration,
+    Collisons,
+    GroundContact,
+    DropDown,
+    JumpControl,
+    PathFollower,
+    Sprite,
+    MovementAnimation,
+    Projectile,
+};
+
+/// The components of every entity at a single point in time. Entities that are deleted after
+/// the snapshot is taken cannot be restored, and entities that are created after the snapshot is
+/// taken are deleted when it is restored. The entities from the map are never deleted since they
+/// are all created before any snapshot can be taken.
+#[derive(Debug, Clone)]
+pub struct ComponentSnapshot {
+    /// Every entity that was alive when the snapshot was taken
+    entities: Vec<Entity>,
+    positions: Vec<(Entity, Position)>,
+    previous_positions: Vec<(Entity, PreviousPosition)>,
+    rotations: Vec<(Entity, Rotation)>,
+    bounding_boxes: Vec<(Entity, BoundingBox)>,
+    densities: Vec<(Entity, Density)>,
+    collision_layers: Vec<(Entity, CollisionLayer)>,
+    velocities: Vec<(Entity, Velocity)>,
+    applied_accel: Vec<(Entity, AppliedAcceleration)>,
+    collisions: Vec<(Entity, Collisons)>,
+    ground_contacts: Vec<(Entity, GroundContact)>,
+    drop_downs: Vec<(Entity, DropDown)>,
+    jump_controls: Vec<(Entity, JumpControl)>,
+    path_followers: Vec<(Entity, PathFollower)>,
+    sprites: Vec<(Entity, Sprite)>,
+    movement_animations: Vec<(Entity, MovementAnimation)>,
+    projectiles: Vec<(Entity, Projectile)>,
+}
+
+impl ComponentSnapshot {
+    pub fn save(world: &World) -> Self {
+        Self {
+            entities: (&*world.entities()).join().collect(),
+            positions: save_storage(world),
+            previous_positions: save_storage(world),
+            rotations: save_storage(world),
+            bounding_boxes: save_storage(world),
+            densities: save_storage(world),
+            collision_layers: save_storage(world),
+            velocities: save_storage(world),
+            applied_accel: save_storage(world),
+            collisions: save_storage(world),
+            ground_contacts: save_storage(world),
+            drop_downs: save_storage(world),
+            jump_controls: save_storage(world),
+            path_followers: save_storage(world),
+            sprites: save_storage(world),
+            movement_animations: save_storage(world),
+            projectiles: save_storage(world),
+        }
+    }
+
+    /// Call World::maintain() afterwards to finish deleting the entities created since the
+    /// snapshot was taken
+    pub fn restore(&self, world: &World) {
+        {
+            let entities = world.entities();
+            let created: Vec<_> = (&*entities).join()
+                .filter(|entity| !self.entities.contains(entity))
+                .collect();
+            for entity in created {
+                entities.delete(entity)
+                    .expect("bug: entity to delete should be alive");
+            }
+        }
+
+        restore_storage(world, &self.positions);
+        restore_storage(world, &self.previous_positions);
+        restore_storage(world, &self.rotations);
+        restore_storage(world, &self.bounding_boxes);
+        restore_storage(world, &self.densities);
+        restore_storage(world, &self.collision_layers);
+        restore_storage(world, &self.velocities);
+        restore_storage(world, &self.applied_accel);
+        restore_storage(world, &self.collisions);
+        restore_storage(world, &self.ground_contacts);
+        restore_storage(world, &self.drop_downs);
+        restore_storage(world, &self.jump_controls);
+        restore_storage(world, &self.path_followers);
+        restore_storage(world, &self.sprites);
+        restore_storage(world, &self.movement_animations);
+        restore_storage(world, &self.projectiles);
+    }
+}
+
+fn save_storage<T: Component + Clone>(world: &World) -> Vec<(Entity, T)> {
+    let entities = world.entities();
+    let storage = world.read_storage::<T>();
+    (&*entities, &storage).join()
+        .map(|(entity, component)| (entity, component.clone()))
+        .collect()
+}
+
+fn restore_storage<T: Component + Clone>(world: &World, saved: &[(Entity, T)]) {
+    let entities = world.entities();
+    let mut storage = world.write_storage::<T>();
+
+    // Anything that didn't have this component when the snapshot was taken shouldn't have it now
+    let extra: Vec<_> = (&*entities, &storage).join()
+        .map(|(entity, _)| entity)
+        .filter(|entity| saved.iter().all(|&(saved_entity, _)| saved_entity != *entity))
+        .collect();
+    for entity in extra {
+        storage.remove(entity);
+    }
+
+    for &(entity, ref component) in saved {
+        if entities.is_alive(entity) {
+            storage.insert(entity, component.clone())
+                .expect("bug: entity should be alive");
+        }
+    }
+}
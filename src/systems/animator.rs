@@ -1,11 +1,9 @@
-use specs::{System, Join, ReadExpect, ReadStorage, WriteStorage};
+use specs::{System, Join, ReadStorage, WriteStorage};
 
 use components::{Velocity, Sprite, MovementAnimation};
-use resources::FramesElapsed;
 
 #[derive(SystemData)]
 pub struct AnimatorData<'a> {
-    frames: ReadExpect<'a, FramesElapsed>,
     velocities: ReadStorage<'a, Velocity>,
     sprites: WriteStorage<'a, Sprite>,
     animations: WriteStorage<'a, MovementAnimation>,
@@ -16,9 +14,7 @@ pub struct Animator;
 impl<'a> System<'a> for Animator {
     type SystemData = AnimatorData<'a>;
 
-    fn run(&mut self, AnimatorData {frames, velocities, mut sprites, mut animations}: Self::SystemData) {
-        let FramesElapsed(frames_elapsed) = *frames;
-
+    fn run(&mut self, AnimatorData {velocities, mut sprites, mut animations}: Self::SystemData) {
         for (&Velocity(vel), sprite, animation) in (&velocities, &mut sprites, &mut animations).join() {
             if vel.x.round() > 0.0 {
                 // The assumption is that the sprite begins facing right
@@ -32,7 +28,8 @@ impl<'a> System<'a> for Animator {
                 continue;
             }
 
-            animation.frame_counter += frames_elapsed;
+            // Systems are run exactly once per frame
+            animation.frame_counter += 1;
             let current_step = animation.frame_counter % (animation.steps.len() * animation.frames_per_step) / animation.frames_per_step;
 
             let (current_texture_id, current_region) = animation.steps[current_step];
@@ -0,0 +1,627 @@
+use std::collections::{HashMap, HashSet, BTreeSet};
+
+use specs::{Entity, System, Join, Read, Write, ReadStorage, WriteStorage, Entities};
+use shrev::EventChannel;
+use nalgebra::{Isometry2, Point2};
+use ncollide2d::shape::{self, Cuboid, ShapeHandle};
+
+use components::{
+    Position,
+    PreviousPosition,
+    Velocity,
+    Collisons,
+    GroundContact,
+    BoundingBox,
+    Density,
+    Kinematic,
+    AppliedAcceleration,
+    DropDown,
+    Suspended,
+    Projectile,
+};
+use resources::{Snapshots, PhysicsDebug, DebugOutline, ProjectileHit};
+use physics_query::{PhysicsQuery, QueryCollider, QueryFilter};
+use config::PhysicsConfig;
+use math::Vec2D;
+use map::{LevelMap, StaticBoundary};
+
+use super::physics::{Physics, move_projectiles};
+
+/// How far (in pixels) below a surface the bottom of a body can be while still being pushed back
+/// on top of it. Only applies to one-way boundaries and surfaces being walked up.
+const ONE_WAY_TOLERANCE: f64 = 2.0;
+/// How far (in pixels) a body on the ground is moved down to stay on a surface that drops away
+/// beneath it (e.g. when walking down a slope)
+const GROUND_SNAP_DISTANCE: f64 = 4.0;
+/// How close (in pixels) a surface needs to be for a body to be considered touching it
+const CONTACT_DISTANCE: f64 = 1.0;
+/// Tiny gap used to avoid floating point error when comparing a body against a surface it is
+/// resting on
+const SKIN: f64 = 0.001;
+
+#[derive(SystemData)]
+pub struct CharacterControllerData<'a> {
+    entities: Entities<'a>,
+    densities: ReadStorage<'a, Density>,
+    kinematics: ReadStorage<'a, Kinematic>,
+    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    applied_accel: ReadStorage<'a, AppliedAcceleration>,
+    positions: WriteStorage<'a, Position>,
+    previous_positions: WriteStorage<'a, PreviousPosition>,
+    velocities: WriteStorage<'a, Velocity>,
+    collisions: WriteStorage<'a, Collisons>,
+    ground_contacts: WriteStorage<'a, GroundContact>,
+    drop_downs: WriteStorage<'a, DropDown>,
+    suspended: ReadStorage<'a, Suspended>,
+    projectiles: WriteStorage<'a, Projectile>,
+    projectile_hits: Write<'a, EventChannel<ProjectileHit>>,
+    snapshots: Read<'a, Snapshots>,
+    query: Write<'a, PhysicsQuery>,
+    config: Read<'a, PhysicsConfig>,
+    debug: Write<'a, PhysicsDebug>,
+}
+
+/// Where a segment came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SegmentOwner {
+    /// The index of the segment in the static boundaries of the map
+    Map(usize),
+    /// A side of the bounding box of a static or kinematic entity
+    Entity(Entity),
+}
+
+/// A single straight line that bodies collide with
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: Point2<f64>,
+    end: Point2<f64>,
+    /// One-way segments can only be landed on from above
+    one_way: bool,
+    owner: SegmentOwner,
+}
+
+impl Segment {
+    /// Returns true if the segment is too steep to stand on
+    fn is_wall(&self) -> bool {
+        let dir = self.end - self.start;
+        dir.y.abs() > dir.x.abs() * CharacterController::MAX_WALKABLE_SLOPE.tan()
+    }
+
+    /// Returns the ground contact for a body standing on this segment
+    fn ground_contact(&self) -> GroundContact {
+        let dir = (self.end - self.start).normalize();
+        // Perpendicular to the segment, pointing up (positive y is downwards)
+        let normal = if dir.x >= 0.0 { Vec2D::new(dir.y, -dir.x) } else { Vec2D::new(-dir.y, dir.x) };
+        let entity = match self.owner {
+            SegmentOwner::Map(_) => None,
+            SegmentOwner::Entity(entity) => Some(entity),
+        };
+        GroundContact::new(normal, entity, CharacterController::MAX_WALKABLE_SLOPE)
+    }
+
+    /// Returns the (highest, lowest) y coordinates of the part of the segment between the given
+    /// x coordinates. Must not be called on vertical segments.
+    fn y_range_within(&self, left: f64, right: f64) -> Option<(f64, f64)> {
+        let start = left.max(self.start.x.min(self.end.x));
+        let end = right.min(self.start.x.max(self.end.x));
+        if start > end {
+            return None;
+        }
+
+        let dir = self.end - self.start;
+        let y_at = |x| self.start.y + (x - self.start.x) * dir.y / dir.x;
+        let (y1, y2) = (y_at(start), y_at(end));
+        Some((y1.min(y2), y1.max(y2)))
+    }
+
+    /// Returns the (leftmost, rightmost) x coordinates of the part of the segment strictly
+    /// between the given y coordinates
+    fn x_range_within(&self, top: f64, bottom: f64) -> Option<(f64, f64)> {
+        let start = top.max(self.start.y.min(self.end.y));
+        let end = bottom.min(self.start.y.max(self.end.y));
+        if start >= end {
+            return None;
+        }
+
+        let dir = self.end - self.start;
+        let x_at = |y| self.start.x + (y - self.start.y) * dir.x / dir.y;
+        let (x1, x2) = (x_at(start), x_at(end));
+        Some((x1.min(x2), x1.max(x2)))
+    }
+}
+
+/// Returns the four sides of a box
+fn box_segments(center: Vec2D, half_size: Vec2D, owner: SegmentOwner) -> [Segment; 4] {
+    let top_left = Point2::from_coordinates(center - half_size);
+    let bottom_right = Point2::from_coordinates(center + half_size);
+    let top_right = Point2::new(bottom_right.x, top_left.y);
+    let bottom_left = Point2::new(top_left.x, bottom_right.y);
+    let side = |start, end| Segment {start, end, one_way: false, owner};
+
+    [
+        side(top_left, top_right),
+        side(top_right, bottom_right),
+        side(bottom_right, bottom_left),
+        side(bottom_left, top_left),
+    ]
+}
+
+/// Returns how far a box can move horizontally before it hits a wall and whether it hit one
+fn sweep_x(segments: &[Segment], center: Vec2D, half_size: Vec2D, dx: f64) -> (f64, bool) {
+    let top = center.y - half_size.y + SKIN;
+    let bottom = center.y + half_size.y - SKIN;
+    let left = center.x - half_size.x;
+    let right = center.x + half_size.x;
+
+    let mut allowed = dx;
+    let mut hit = false;
+    for segment in segments.iter().filter(|segment| segment.is_wall() && !segment.one_way) {
+        let (wall_left, wall_right) = match segment.x_range_within(top, bottom) {
+            Some(range) => range,
+            None => continue,
+        };
+
+        if dx > 0.0 && wall_left >= right - SKIN && wall_left - right <= allowed {
+            allowed = (wall_left - right).max(0.0);
+            hit = true;
+        }
+        else if dx < 0.0 && wall_right <= left + SKIN && wall_right - left >= allowed {
+            allowed = (wall_right - left).min(0.0);
+            hit = true;
+        }
+    }
+
+    (allowed, hit)
+}
+
+/// Returns how far a box can move down before it lands on a surface and the surface it landed
+/// on (if any). The box may be moved up by at most `step_up` in order to get on top of a surface
+/// that it is partially inside.
+fn sweep_down<F>(
+    segments: &[Segment],
+    center: Vec2D,
+    half_size: Vec2D,
+    dy: f64,
+    step_up: f64,
+    ignore: F,
+) -> (f64, Option<Segment>)
+    where F: Fn(&Segment) -> bool,
+{
+    let left = center.x - half_size.x + SKIN;
+    let right = center.x + half_size.x - SKIN;
+    let bottom = center.y + half_size.y;
+
+    let mut allowed = dy;
+    let mut ground = None;
+    for segment in segments.iter().filter(|segment| !segment.is_wall() && !ignore(*segment)) {
+        let (surface, _) = match segment.y_range_within(left, right) {
+            Some(range) => range,
+            None => continue,
+        };
+
+        if bottom <= surface + step_up && surface - bottom <= allowed {
+            allowed = surface - bottom;
+            ground = Some(*segment);
+        }
+    }
+
+    (allowed, ground)
+}
+
+/// Returns how far a box can move up before it hits a surface and whether it hit one
+fn sweep_up(segments: &[Segment], center: Vec2D, half_size: Vec2D, dy: f64) -> (f64, bool) {
+    let left = center.x - half_size.x + SKIN;
+    let right = center.x + half_size.x - SKIN;
+    let top = center.y - half_size.y;
+
+    let mut allowed = dy;
+    let mut hit = false;
+    for segment in segments.iter().filter(|segment| !segment.is_wall() && !segment.one_way) {
+        let (_, ceiling) = match segment.y_range_within(left, right) {
+            Some(range) => range,
+            None => continue,
+        };
+
+        if ceiling <= top + SKIN && ceiling - top >= allowed {
+            allowed = (ceiling - top).min(0.0);
+            hit = true;
+        }
+    }
+
+    (allowed, hit)
+}
+
+/// Returns the (column, row) of the tile grid cell containing the given point
+fn grid_cell(point: Vec2D, cell_size: Vec2D) -> (i32, i32) {
+    ((point.x / cell_size.x).floor() as i32, (point.y / cell_size.y).floor() as i32)
+}
+
+/// A deterministic alternative to the Physics system that is much cheaper to run. Bodies are
+/// moved with swept bounding boxes against the static boundaries in the map (looked up through
+/// the tile grid) and the bounding boxes of static and kinematic entities. Surfaces up to
+/// MAX_WALKABLE_SLOPE can be walked up and down, while steeper surfaces act as walls.
+///
+/// Fills in Position, PreviousPosition, Velocity, Collisons, GroundContact and the PhysicsQuery
+/// resource and moves Projectiles just like the Physics system. The PhysicsDebug resource only gets colliders and
+/// velocities since there are no sensors, triggers, fluids or contact points. Does not support
+/// friction, restitution, collisions between two rigid bodies, triggers, fluids, collision layers
+/// or collision events. Bodies never rotate.
+pub struct CharacterController {
+    /// The time that passes in a single frame (seconds)
+    timestep: f64,
+    /// Every segment of every static boundary in the map
+    segments: Vec<Segment>,
+    /// The size of each cell in the tile grid
+    cell_size: Vec2D,
+    /// The indexes of the segments that pass through each (column, row) cell of the tile grid
+    grid: HashMap<(i32, i32), Vec<usize>>,
+    /// The entities that were on the ground at the end of the last frame
+    grounded: HashSet<Entity>,
+    /// Maps each entity standing on a kinematic body to that kinematic body. Anything standing on
+    /// a kinematic body is carried along with it.
+    riders: HashMap<Entity, Entity>,
+    /// The one-way segments that each entity has chosen to drop down through
+    dropping: HashMap<Entity, HashSet<usize>>,
+    /// A query collider for every segment in the map, since those never change
+    map_colliders: Vec<QueryCollider>,
+}
+
+impl CharacterController {
+    /// Surfaces steeper than this (radians from horizontal) are walls
+    pub const MAX_WALKABLE_SLOPE: f64 = Physics::MAX_WALKABLE_SLOPE;
+
+    pub fn new(fps: f64, map: &LevelMap) -> Self {
+        let (tile_width, tile_height) = map.tile_size();
+        let cell_size = Vec2D::new(tile_width as f64, tile_height as f64);
+
+        let mut segments = Vec::new();
+        let mut grid = HashMap::new();
+        for &StaticBoundary {ref points, one_way, ..} in map.static_boundaries() {
+            for line in points.windows(2) {
+                let (start, end) = (line[0], line[1]);
+                if start == end {
+                    continue;
+                }
+
+                let index = segments.len();
+                segments.push(Segment {start, end, one_way, owner: SegmentOwner::Map(index)});
+
+                // Add the segment to every cell its bounding box overlaps
+                let (min_col, min_row) = grid_cell(Vec2D::new(start.x.min(end.x), start.y.min(end.y)), cell_size);
+                let (max_col, max_row) = grid_cell(Vec2D::new(start.x.max(end.x), start.y.max(end.y)), cell_size);
+                for row in min_row..=max_row {
+                    for col in min_col..=max_col {
+                        grid.entry((col, row)).or_insert_with(Vec::new).push(index);
+                    }
+                }
+            }
+        }
+
+        let map_colliders = segments.iter().map(|segment| QueryCollider {
+            shape: ShapeHandle::new(shape::Segment::new(segment.start, segment.end)),
+            position: Isometry2::identity(),
+            entity: None,
+            one_way: segment.one_way,
+        }).collect();
+
+        Self {
+            timestep: 1.0 / fps,
+            segments,
+            cell_size,
+            grid,
+            grounded: Default::default(),
+            riders: Default::default(),
+            dropping: Default::default(),
+            map_colliders,
+        }
+    }
+
+    /// Returns every segment that a body could touch while moving by the given displacement.
+    /// Segments are always returned in the same order so that the results are deterministic.
+    fn nearby_segments(
+        &self,
+        center: Vec2D,
+        half_size: Vec2D,
+        displacement: Vec2D,
+        entity_segments: &[Segment],
+    ) -> Vec<Segment> {
+        // Far enough to include stepping up slopes, snapping to the ground and touching surfaces
+        let margin = GROUND_SNAP_DISTANCE + CONTACT_DISTANCE;
+        let reach = half_size + Vec2D::new(
+            displacement.x.abs() * 2.0 + margin,
+            displacement.y.abs() * 2.0 + margin,
+        );
+        let (min_col, min_row) = grid_cell(center - reach, self.cell_size);
+        let (max_col, max_row) = grid_cell(center + reach, self.cell_size);
+
+        let mut indexes = BTreeSet::new();
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                if let Some(cell) = self.grid.get(&(col, row)) {
+                    indexes.extend(cell);
+                }
+            }
+        }
+
+        indexes.into_iter()
+            .map(|index| self.segments[index])
+            .chain(entity_segments.iter().cloned())
+            .collect()
+    }
+
+    /// Replaces the contents of the given debug info with the segments in the map, the bounding
+    /// box of every entity and the velocity of every rigid body
+    fn update_debug(
+        &self,
+        debug: &mut PhysicsDebug,
+        entities: &Entities,
+        densities: &ReadStorage<Density>,
+        kinematics: &ReadStorage<Kinematic>,
+        bounding_boxes: &ReadStorage<BoundingBox>,
+        positions: &WriteStorage<Position>,
+        velocities: &WriteStorage<Velocity>,
+    ) {
+        debug.colliders.clear();
+        debug.sensors.clear();
+        debug.triggers.clear();
+        debug.fluids.clear();
+        debug.velocities.clear();
+        debug.contacts.clear();
+
+        debug.colliders.extend(self.segments.iter().map(|segment| DebugOutline {
+            points: vec![segment.start, segment.end],
+            closed: false,
+        }));
+
+        for (entity, &BoundingBox {width, height}, &Position(pos)) in (&**entities, bounding_boxes, positions).join() {
+            let half_size = Vec2D::new(width as f64 / 2.0, height as f64 / 2.0);
+            let sides = box_segments(pos, half_size, SegmentOwner::Entity(entity));
+            debug.colliders.push(DebugOutline {
+                points: sides.iter().map(|side| side.start).collect(),
+                closed: true,
+            });
+
+            if densities.get(entity).is_none() || kinematics.get(entity).is_some() {
+                continue;
+            }
+            if let Some(&Velocity(vel)) = velocities.get(entity) {
+                debug.velocities.push((Point2::from_coordinates(pos), vel));
+            }
+        }
+    }
+}
+
+impl<'a> System<'a> for CharacterController {
+    type SystemData = CharacterControllerData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let CharacterControllerData {
+            entities,
+            densities,
+            kinematics,
+            bounding_boxes,
+            applied_accel,
+            mut positions,
+            mut previous_positions,
+            mut velocities,
+            mut collisions,
+            mut ground_contacts,
+            mut drop_downs,
+            suspended,
+            mut projectiles,
+            mut projectile_hits,
+            snapshots,
+            mut query,
+            config,
+            mut debug,
+        } = data;
+        let timestep = self.timestep;
+
+        // Forget about anything that has been deleted or no longer has a bounding box
+        self.grounded.retain(|&entity| bounding_boxes.get(entity).is_some());
+        self.riders.retain(|&rider, &mut platform| bounding_boxes.get(rider).is_some() && bounding_boxes.get(platform).is_some());
+        self.dropping.retain(|&entity, _| bounding_boxes.get(entity).is_some());
+
+        // The new center of each entity moved by this system
+        let mut centers = HashMap::new();
+
+        // Kinematic bodies move at exactly the velocity they were given. Suspended entities don't
+        // move at all.
+        let kinematic_bodies: Vec<_> = (&*entities, &kinematics, &bounding_boxes, &positions, &velocities, !&suspended).join()
+            .map(|(entity, _, _, &Position(pos), &Velocity(vel), _)| (entity, pos, vel))
+            .collect();
+        let mut displacements = HashMap::new();
+        for (entity, pos, vel) in kinematic_bodies {
+            let displacement = vel * timestep;
+            centers.insert(entity, pos + displacement);
+            displacements.insert(entity, displacement);
+        }
+
+        // Static and kinematic entities collide using the sides of their bounding boxes
+        let mut entity_segments = Vec::new();
+        for (entity, &BoundingBox {width, height}, &Position(pos)) in (&*entities, &bounding_boxes, &positions).join() {
+            if densities.get(entity).is_some() && kinematics.get(entity).is_none() {
+                continue;
+            }
+
+            let center = centers.get(&entity).cloned().unwrap_or(pos);
+            let half_size = Vec2D::new(width as f64 / 2.0, height as f64 / 2.0);
+            entity_segments.extend(&box_segments(center, half_size, SegmentOwner::Entity(entity)));
+        }
+
+        let rigid_bodies: Vec<_> = (&*entities, &densities, &bounding_boxes, &positions).join()
+            .filter(|&(entity, _, _, _)| kinematics.get(entity).is_none() && suspended.get(entity).is_none())
+            .map(|(entity, _, &BoundingBox {width, height}, &Position(pos))| {
+                (entity, Vec2D::new(width as f64 / 2.0, height as f64 / 2.0), pos)
+            })
+            .collect();
+        for &(entity, half_size, pos) in &rigid_bodies {
+            let mut center = pos;
+            let Velocity(vel) = velocities.get_mut(entity)
+                .expect("Rigid body should have had a velocity");
+
+            let accel = applied_accel.get(entity)
+                .map_or_else(Vec2D::zeros, |&AppliedAcceleration(accel)| accel);
+            *vel += (Vec2D::y() * config.gravity_accel + accel) * timestep;
+
+            // Carry anything standing on a kinematic body along with it
+            if let Some(platform) = self.riders.get(&entity) {
+                center += displacements.get(platform).cloned().unwrap_or_else(Vec2D::zeros);
+            }
+
+            let segments = self.nearby_segments(center, half_size, *vel * timestep, &entity_segments);
+
+            // Decide which one-way segments this body should drop down through
+            let drop_down = drop_downs.remove(entity).is_some();
+            {
+                let dropping = self.dropping.entry(entity).or_insert_with(HashSet::new);
+                let bottom = center.y + half_size.y;
+                for segment in segments.iter().filter(|segment| segment.one_way && !segment.is_wall()) {
+                    let index = match segment.owner {
+                        SegmentOwner::Map(index) => index,
+                        SegmentOwner::Entity(_) => continue,
+                    };
+                    let surface = segment.y_range_within(center.x - half_size.x, center.x + half_size.x)
+                        .map(|(surface, _)| surface);
+                    let below = surface.map_or(true, |surface| bottom > surface + ONE_WAY_TOLERANCE);
+
+                    if below {
+                        // Passing through normally from now on
+                        dropping.remove(&index);
+                    }
+                    // Only drop through the segment being stood on, not every one further down
+                    else if drop_down && surface.map_or(false, |surface| (bottom - surface).abs() <= ONE_WAY_TOLERANCE) {
+                        dropping.insert(index);
+                    }
+                }
+            }
+            let dropping = &self.dropping[&entity];
+            let ignore = |segment: &Segment| match segment.owner {
+                SegmentOwner::Map(index) => dropping.contains(&index),
+                SegmentOwner::Entity(_) => false,
+            };
+
+            let (dx, hit_wall) = sweep_x(&segments, center, half_size, vel.x * timestep);
+            center.x += dx;
+            if hit_wall {
+                vel.x = 0.0;
+            }
+
+            let dy = vel.y * timestep;
+            if dy >= 0.0 {
+                // Walking up a slope moves the body into it, so allow the body to be pushed up
+                let step_up = (dx.abs() * Self::MAX_WALKABLE_SLOPE.tan()).max(ONE_WAY_TOLERANCE);
+                let (mut allowed, mut ground) = sweep_down(&segments, center, half_size, dy, step_up, &ignore);
+                if ground.is_none() && self.grounded.contains(&entity) {
+                    // Stay on the ground when walking down slopes
+                    let (snap, snap_ground) = sweep_down(&segments, center, half_size, dy + GROUND_SNAP_DISTANCE, step_up, &ignore);
+                    if snap_ground.is_some() {
+                        allowed = snap;
+                        ground = snap_ground;
+                    }
+                }
+
+                center.y += allowed;
+                if ground.is_some() {
+                    vel.y = 0.0;
+                }
+            }
+            else {
+                let (allowed, hit_ceiling) = sweep_up(&segments, center, half_size, dy);
+                center.y += allowed;
+                if hit_ceiling {
+                    vel.y = 0.0;
+                }
+            }
+
+            // Find out what the body is touching now that it has moved
+            let (_, ground) = sweep_down(&segments, center, half_size, CONTACT_DISTANCE, SKIN, &ignore);
+            let (_, top) = sweep_up(&segments, center, half_size, -CONTACT_DISTANCE);
+            let (_, left) = sweep_x(&segments, center, half_size, -CONTACT_DISTANCE);
+            let (_, right) = sweep_x(&segments, center, half_size, CONTACT_DISTANCE);
+            if let Some(collisions) = collisions.get_mut(entity) {
+                *collisions = Collisons {top, left, right, bottom: ground.is_some()};
+
+                match ground {
+                    Some(segment) => {
+                        ground_contacts.insert(entity, segment.ground_contact())
+                            .expect("bug: entity moved by the character controller should still be alive");
+                    },
+                    None => {
+                        ground_contacts.remove(entity);
+                    },
+                }
+            }
+
+            let platform = ground.and_then(|segment| match segment.owner {
+                SegmentOwner::Entity(owner) if kinematics.get(owner).is_some() => Some(owner),
+                _ => None,
+            });
+            match platform {
+                Some(platform) => {
+                    self.riders.insert(entity, platform);
+                },
+                None => {
+                    self.riders.remove(&entity);
+                },
+            }
+            if ground.is_some() {
+                self.grounded.insert(entity);
+            }
+            else {
+                self.grounded.remove(&entity);
+            }
+
+            centers.insert(entity, center);
+        }
+
+        // Update every moved entity with its new position
+        for (&entity, &center) in &centers {
+            let Position(position) = positions.get_mut(entity)
+                .expect("Moving body should have had a position");
+            previous_positions.insert(entity, PreviousPosition(*position))
+                .expect("bug: entity moved by the character controller should still be alive");
+            *position = center;
+        }
+
+        let mut query_colliders = self.map_colliders.clone();
+        for (entity, &BoundingBox {width, height}, &Position(pos)) in (&*entities, &bounding_boxes, &positions).join() {
+            query_colliders.push(QueryCollider {
+                shape: ShapeHandle::new(Cuboid::new(Vec2D::new(width as f64 / 2.0, height as f64 / 2.0))),
+                position: Isometry2::new(pos, 0.0),
+                entity: Some(entity),
+                one_way: false,
+            });
+        }
+        query.update(query_colliders);
+
+        // Projectiles hit everything where it is now, just like with the Physics system
+        let filter = QueryFilter::default();
+        let mut hits = move_projectiles(
+            timestep,
+            &entities,
+            &mut projectiles,
+            &mut positions,
+            &mut previous_positions,
+            &velocities,
+            |_, ray, distance| {
+                query.cast_ray_all(ray.origin.coords, ray.dir, distance, &filter).into_iter()
+                    .map(|hit| (hit.distance, hit.normal, hit.entity))
+                    .collect()
+            },
+        );
+        projectile_hits.drain_vec_write(&mut hits);
+
+        if debug.enabled {
+            self.update_debug(&mut debug, &entities, &densities, &kinematics, &bounding_boxes, &positions, &velocities);
+        }
+
+        // Snapshots only save the components of each entity, so start over from those
+        // components both when saving and restoring to make the frames afterwards the same
+        if snapshots.request.is_some() {
+            self.grounded.clear();
+            self.riders.clear();
+            self.dropping.clear();
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use specs::{System, Join, Read, ReadExpect, ReadStorage, WriteStorage, Entities};
+use sdl2::rect::Point;
+
+use components::{Position, BoundingBox, CameraFocus, Suspended};
+use resources::Camera;
+use config::PhysicsConfig;
+use map::LevelMap;
+
+#[derive(SystemData)]
+pub struct CullingData<'a> {
+    entities: Entities<'a>,
+    camera: Read<'a, Camera>,
+    map: ReadExpect<'a, LevelMap>,
+    config: Read<'a, PhysicsConfig>,
+    positions: ReadStorage<'a, Position>,
+    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    camera_focuses: ReadStorage<'a, CameraFocus>,
+    suspended: WriteStorage<'a, Suspended>,
+}
+
+/// Suspends every entity with a bounding box that is further than the activation margin outside
+/// of the camera's view and resumes it once the camera gets close enough again. The view is
+/// found from the Position of the camera focus (not where the renderer last drew it) so that the
+/// same frames always suspend the same entities.
+#[derive(Default)]
+pub struct Culling;
+
+impl<'a> System<'a> for Culling {
+    type SystemData = CullingData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let CullingData {
+            entities,
+            camera,
+            map,
+            config,
+            positions,
+            bounding_boxes,
+            camera_focuses,
+            mut suspended,
+        } = data;
+
+        // Everything is simulated until there is something for the camera to focus on
+        let focus = match (&positions, &camera_focuses).join().next() {
+            Some((&Position(pos), _)) => Point::new(pos.x.round() as i32, pos.y.round() as i32),
+            None => return,
+        };
+        let view = camera.view(focus, map.level_boundary());
+        let margin = config.activation_margin;
+        let left = view.left() as f64 - margin;
+        let right = view.right() as f64 + margin;
+        let top = view.top() as f64 - margin;
+        let bottom = view.bottom() as f64 + margin;
+
+        for (entity, &Position(pos), &BoundingBox {width, height}) in (&*entities, &positions, &bounding_boxes).join() {
+            let (half_width, half_height) = (width as f64 / 2.0, height as f64 / 2.0);
+            let nearby = pos.x + half_width >= left && pos.x - half_width <= right
+                && pos.y + half_height >= top && pos.y - half_height <= bottom;
+
+            // The camera is always near whatever it is focused on
+            if nearby || camera_focuses.get(entity).is_some() {
+                suspended.remove(entity);
+            }
+            else if suspended.get(entity).is_none() {
+                suspended.insert(entity, Suspended)
+                    .expect("bug: entity being culled should be alive");
+            }
+        }
+    }
+}
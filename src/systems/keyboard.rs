@@ -1,48 +1,268 @@
-use specs::{System, Join, ReadExpect, ReadStorage, WriteStorage};
+use specs::{System, Join, Read, ReadExpect, ReadStorage, WriteStorage, Entities};
 
-use components::{Collisons, AppliedAcceleration, KeyboardControlled};
+use components::{Collisons, Velocity, AppliedAcceleration, KeyboardControlled, JumpControl, DropDown};
 use resources::GameKeys;
-
-use super::physics::Physics;
+use config::{PhysicsConfig, MovementConfig};
 
 #[derive(SystemData)]
 pub struct KeyboardData<'a> {
+    entities: Entities<'a>,
     keys: ReadExpect<'a, GameKeys>,
     collisions: ReadStorage<'a, Collisons>,
+    velocities: ReadStorage<'a, Velocity>,
     keyboard_controlled: ReadStorage<'a, KeyboardControlled>,
     applied_accel: WriteStorage<'a, AppliedAcceleration>,
+    jump_controls: WriteStorage<'a, JumpControl>,
+    drop_downs: WriteStorage<'a, DropDown>,
+    physics_config: Read<'a, PhysicsConfig>,
+    movement_config: Read<'a, MovementConfig>,
 }
 
-#[derive(Default)]
+/// Moves keyboard controlled entities by setting their AppliedAcceleration
 pub struct Keyboard {
-    jumping: bool,
+    /// The time that passes in a single frame (seconds)
+    timestep: f64,
+}
+
+impl Keyboard {
+    pub fn new(fps: f64) -> Self {
+        Self {
+            timestep: 1.0 / fps,
+        }
+    }
 }
 
 impl<'a> System<'a> for Keyboard {
     type SystemData = KeyboardData<'a>;
 
-    fn run(&mut self, KeyboardData {keys, collisions, keyboard_controlled, mut applied_accel}: Self::SystemData) {
-        for (AppliedAcceleration(ref mut accel), collisions, _) in (&mut applied_accel, &collisions, &keyboard_controlled).join() {
+    fn run(&mut self, data: Self::SystemData) {
+        let KeyboardData {
+            entities,
+            keys,
+            collisions,
+            velocities,
+            keyboard_controlled,
+            mut applied_accel,
+            mut jump_controls,
+            mut drop_downs,
+            physics_config,
+            movement_config,
+        } = data;
+        let gravity_accel = physics_config.gravity_accel;
+        let jump_tuning = movement_config.jump;
+
+        for (entity, AppliedAcceleration(ref mut accel), collisions, &Velocity(vel), _) in (&*entities, &mut applied_accel, &collisions, &velocities, &keyboard_controlled).join() {
             // Assuming that only a single arrow key can be held down at a time.
-            if keys.right_arrow {
-                accel.x = 100.0;
+            let direction = if keys.right_arrow {
+                1.0
             }
             else if keys.left_arrow {
-                accel.x = -100.0;
+                -1.0
             }
             else {
-                accel.x = 0.0;
+                0.0
+            };
+
+            let on_ground = collisions.bottom;
+            accel.x = movement_config.horizontal_accel(vel.x, direction, on_ground, self.timestep);
+
+            let jump = match jump_controls.get_mut(entity) {
+                Some(jump) => jump,
+                // Entities without a JumpControl can only walk
+                None => {
+                    accel.y = 0.0;
+                    continue;
+                },
+            };
+            let jump_pressed = keys.b && !jump.button_held;
+            jump.button_held = keys.b;
+            // Positive y is downwards
+            let moving_up = vel.y < 0.0;
+
+            if on_ground && jump.hold_frames.is_none() {
+                jump.hover_frames_left = jump_tuning.max_hover_frames;
+                jump.coyote_frames_left = jump_tuning.coyote_frames;
+            }
+            // Jumps are allowed for a little while after leaving the ground
+            let can_jump = on_ground || jump.coyote_frames_left > 0;
+            if !on_ground {
+                jump.coyote_frames_left = jump.coyote_frames_left.saturating_sub(1);
             }
 
-            if keys.b && collisions.bottom && !self.jumping {
-                // Must overcome gravity and then accelerate even more
-                accel.y = -(Physics::GRAVITY_ACCEL + 4000.0);
-                self.jumping = true;
+            // Jumps pressed a little too early still happen once a jump is allowed
+            let wants_jump = jump_pressed || jump.buffered_frames_left > 0;
+            if jump_pressed {
+                jump.buffered_frames_left = jump_tuning.jump_buffer_frames;
             }
             else {
+                jump.buffered_frames_left = jump.buffered_frames_left.saturating_sub(1);
+            }
+
+            let continue_jump = match jump.hold_frames {
+                Some(frames) => keys.b && moving_up && frames < jump_tuning.max_hold_frames,
+                None => false,
+            };
+
+            if keys.b && keys.down_arrow && on_ground {
+                // Drop down through one-way platforms instead of jumping
+                drop_downs.insert(entity, DropDown)
+                    .expect("bug: keyboard controlled entity should be alive");
+                jump.hold_frames = None;
+                jump.coyote_frames_left = 0;
                 accel.y = 0.0;
-                self.jumping = false;
             }
+            else if wants_jump && can_jump && jump.hold_frames.is_none() {
+                // Must overcome gravity and then accelerate even more
+                jump.hold_frames = Some(1);
+                jump.coyote_frames_left = 0;
+                jump.buffered_frames_left = 0;
+                accel.y = -(gravity_accel + jump_tuning.start_accel);
+            }
+            else if continue_jump {
+                // The longer the button is held, the higher the jump
+                jump.hold_frames = jump.hold_frames.map(|frames| frames + 1);
+                accel.y = -jump_tuning.hold_accel;
+            }
+            else {
+                jump.hold_frames = None;
+
+                if !on_ground && !keys.b && moving_up {
+                    // Released early, so cut the jump short
+                    accel.y = jump_tuning.release_accel;
+                }
+                else if !on_ground && keys.b && !moving_up && jump.hover_frames_left > 0 {
+                    // Hover by cancelling out some of gravity
+                    jump.hover_frames_left -= 1;
+                    accel.y = -gravity_accel * (1.0 - jump_tuning.hover_gravity_scale);
+                }
+                else {
+                    accel.y = 0.0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use specs::{World, Builder, Entity, RunNow};
+
+    use math::Vec2D;
+
+    const FPS: f64 = 60.0;
+
+    fn setup() -> (World, Entity) {
+        let mut world = World::new();
+        world.register::<Collisons>();
+        world.register::<Velocity>();
+        world.register::<AppliedAcceleration>();
+        world.register::<KeyboardControlled>();
+        world.register::<JumpControl>();
+        world.register::<DropDown>();
+        world.add_resource(GameKeys::default());
+        world.add_resource(PhysicsConfig::default());
+        world.add_resource(MovementConfig::default());
+
+        let player = world.create_entity()
+            .with(Collisons::default())
+            .with(Velocity(Vec2D::zeros()))
+            .with(AppliedAcceleration(Vec2D::zeros()))
+            .with(KeyboardControlled)
+            .with(JumpControl::default())
+            .build();
+        (world, player)
+    }
+
+    /// Runs a single frame with the given keys and returns true if a jump started
+    fn step(keyboard: &mut Keyboard, world: &mut World, player: Entity, jump_key: bool, on_ground: bool) -> bool {
+        world.write_resource::<GameKeys>().b = jump_key;
+        world.write_storage::<Collisons>().get_mut(player).unwrap().bottom = on_ground;
+
+        keyboard.run_now(&world.res);
+
+        let gravity_accel = world.read_resource::<PhysicsConfig>().gravity_accel;
+        let start_accel = world.read_resource::<MovementConfig>().jump.start_accel;
+        let &AppliedAcceleration(accel) = world.read_storage::<AppliedAcceleration>().get(player).unwrap();
+        accel.y == -(gravity_accel + start_accel)
+    }
+
+    /// Walks off a ledge, then presses jump after the given number of frames in the air. Returns
+    /// true if the jump started.
+    fn jump_after_leaving_ground(frames_in_air: usize) -> bool {
+        let (mut world, player) = setup();
+        let mut keyboard = Keyboard::new(FPS);
+
+        assert!(!step(&mut keyboard, &mut world, player, false, true));
+        for _ in 1..frames_in_air {
+            assert!(!step(&mut keyboard, &mut world, player, false, false));
+        }
+        step(&mut keyboard, &mut world, player, true, false)
+    }
+
+    /// Presses jump while in the air, then lands after the given number of frames. Returns true if
+    /// the jump started on the frame of the landing.
+    fn land_after_pressing_jump(frames_before_landing: usize) -> bool {
+        let (mut world, player) = setup();
+        let mut keyboard = Keyboard::new(FPS);
+
+        assert!(!step(&mut keyboard, &mut world, player, true, false));
+        for _ in 1..frames_before_landing {
+            assert!(!step(&mut keyboard, &mut world, player, false, false));
+        }
+        step(&mut keyboard, &mut world, player, false, true)
+    }
+
+    #[test]
+    fn walking_accelerates_with_movement_config() {
+        let (mut world, player) = setup();
+        let mut keyboard = Keyboard::new(FPS);
+        let config = MovementConfig::default();
+
+        for &(right_arrow, left_arrow, direction) in &[(true, false, 1.0), (false, true, -1.0), (false, false, 0.0)] {
+            for &vel_x in &[-120.0, -50.0, 0.0, 50.0, 89.5, 120.0] {
+                for &on_ground in &[true, false] {
+                    {
+                        let mut keys = world.write_resource::<GameKeys>();
+                        keys.right_arrow = right_arrow;
+                        keys.left_arrow = left_arrow;
+                    }
+                    world.write_storage::<Velocity>().get_mut(player).unwrap().0.x = vel_x;
+                    world.write_storage::<Collisons>().get_mut(player).unwrap().bottom = on_ground;
+
+                    keyboard.run_now(&world.res);
+
+                    let &AppliedAcceleration(accel) = world.read_storage::<AppliedAcceleration>().get(player).unwrap();
+                    assert_eq!(accel.x, config.horizontal_accel(vel_x, direction, on_ground, 1.0 / FPS),
+                        "wrong acceleration for direction {} at {} pixels / second", direction, vel_x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jump_starts_within_coyote_frames() {
+        let coyote_frames = MovementConfig::default().jump.coyote_frames;
+        assert!(coyote_frames > 0);
+
+        for frames_in_air in 1..coyote_frames + 1 {
+            assert!(jump_after_leaving_ground(frames_in_air), "no jump after {} frames in the air", frames_in_air);
+        }
+        assert!(!jump_after_leaving_ground(coyote_frames + 1));
+        assert!(!jump_after_leaving_ground(coyote_frames + 10));
+    }
+
+    #[test]
+    fn buffered_jump_starts_on_landing() {
+        let jump_buffer_frames = MovementConfig::default().jump.jump_buffer_frames;
+        assert!(jump_buffer_frames > 0);
+
+        for frames_before_landing in 1..jump_buffer_frames + 1 {
+            assert!(land_after_pressing_jump(frames_before_landing),
+                "no jump when landing {} frames after the press", frames_before_landing);
         }
+        assert!(!land_after_pressing_jump(jump_buffer_frames + 1));
+        assert!(!land_after_pressing_jump(jump_buffer_frames + 10));
     }
 }
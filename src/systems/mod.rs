@@ -1,7 +1,13 @@
 mod animator;
+mod character_controller;
+mod culling;
 mod keyboard;
+mod path_following;
 mod physics;
 
 pub use self::animator::*;
+pub use self::character_controller::*;
+pub use self::culling::*;
 pub use self::keyboard::*;
+pub use self::path_following::*;
 pub use self::physics::*;
@@ -0,0 +1,82 @@
+use specs::{System, Join, ReadStorage, WriteStorage};
+
+use components::{Position, Velocity, Kinematic, PathFollower, PathMode, Suspended};
+use math::Vec2D;
+
+#[derive(SystemData)]
+pub struct PathFollowingData<'a> {
+    kinematics: ReadStorage<'a, Kinematic>,
+    positions: ReadStorage<'a, Position>,
+    suspended: ReadStorage<'a, Suspended>,
+    path_followers: WriteStorage<'a, PathFollower>,
+    velocities: WriteStorage<'a, Velocity>,
+}
+
+/// Sets the velocity of every kinematic entity with a path so that it moves along that path
+pub struct PathFollowing {
+    /// The time that passes in a single frame (seconds)
+    timestep: f64,
+}
+
+impl PathFollowing {
+    pub fn new(fps: f64) -> Self {
+        Self {
+            timestep: 1.0 / fps,
+        }
+    }
+}
+
+impl<'a> System<'a> for PathFollowing {
+    type SystemData = PathFollowingData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let PathFollowingData {kinematics, positions, suspended, mut path_followers, mut velocities} = data;
+
+        // Suspended entities stay exactly where they are in their path until they are resumed
+        for (_, &Position(pos), follower, Velocity(vel), _) in (&kinematics, &positions, &mut path_followers, &mut velocities, !&suspended).join() {
+            if follower.wait_counter > 0 {
+                follower.wait_counter -= 1;
+                *vel = Vec2D::zeros();
+                continue;
+            }
+
+            let to_target = follower.path[follower.next_point] - pos;
+            let distance = to_target.norm();
+            // If the target can be reached in this frame, stop exactly on it so no error builds up
+            // from overshooting
+            if distance <= follower.speed * self.timestep {
+                *vel = to_target / self.timestep;
+                advance(follower);
+                follower.wait_counter = follower.wait_frames;
+            }
+            else {
+                *vel = to_target / distance * follower.speed;
+            }
+        }
+    }
+}
+
+/// Moves on to the next point in the path
+fn advance(follower: &mut PathFollower) {
+    let last = follower.path.len() - 1;
+    match follower.mode {
+        PathMode::Loop => {
+            follower.next_point = (follower.next_point + 1) % follower.path.len();
+        },
+        PathMode::PingPong => {
+            if follower.reversed && follower.next_point == 0 {
+                follower.reversed = false;
+            }
+            else if !follower.reversed && follower.next_point == last {
+                follower.reversed = true;
+            }
+
+            if follower.reversed {
+                follower.next_point -= 1;
+            }
+            else {
+                follower.next_point += 1;
+            }
+        },
+    }
+}
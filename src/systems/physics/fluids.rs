@@ -0,0 +1,109 @@
+use std::collections::{HashMap, HashSet};
+
+use specs::{Entity, ReadStorage};
+use nalgebra::Point2;
+
+use components::{BoundingBox, Density, Kinematic};
+use resources::{FluidEvent, DebugOutline};
+use map::FluidRegion;
+use math::Vec2D;
+
+use super::{Physics, Body};
+
+impl Physics {
+    /// Returns the acceleration caused by buoyancy and drag for every dynamic rigid body that is
+    /// at least partly in a fluid. Also queues up an event for every body that entered or left a
+    /// fluid since the last time this was called.
+    pub(super) fn fluid_accelerations(
+        &mut self,
+        bounding_boxes: &ReadStorage<BoundingBox>,
+        densities: &ReadStorage<Density>,
+        kinematics: &ReadStorage<Kinematic>,
+    ) -> HashMap<Entity, Vec2D> {
+        let mut accelerations = HashMap::new();
+        let mut submerged = HashSet::new();
+
+        for (&entity, body) in &self.bodies {
+            let body_handle = match *body {
+                Body::RigidBody {body_handle, ..} if kinematics.get(entity).is_none() => body_handle,
+                _ => continue,
+            };
+            let (width, height, density) = match (bounding_boxes.get(entity), densities.get(entity)) {
+                (Some(&BoundingBox {width, height}), Some(&Density(density))) => (width, height, density),
+                _ => continue,
+            };
+
+            let rigid_body = self.world.rigid_body(body_handle)
+                .expect("Body handle did not map to a rigid body");
+            let center = rigid_body.position().translation.vector;
+            let velocity = rigid_body.velocity().linear;
+            let half_size = Vec2D::new(width as f64 / 2.0, height as f64 / 2.0);
+
+            let mut accel = Vec2D::zeros();
+            for (index, fluid) in self.fluids.iter().enumerate() {
+                let fraction = submerged_fraction(center, half_size, fluid);
+                if fraction <= 0.0 {
+                    continue;
+                }
+                submerged.insert((entity, index));
+
+                // Pushed up by the weight of the fluid that the body takes the place of
+                accel -= Vec2D::y() * self.config.gravity_accel * fluid.density / density * fraction;
+                // The deeper the body is, the more it gets slowed down
+                accel -= velocity * fluid.drag * fraction;
+            }
+
+            if accel != Vec2D::zeros() {
+                accelerations.insert(entity, accel);
+            }
+        }
+
+        // Sorted so that events are always published in the same order
+        let mut entered: Vec<_> = submerged.difference(&self.submerged).cloned().collect();
+        entered.sort();
+        let mut left: Vec<_> = self.submerged.difference(&submerged).cloned()
+            // Anything removed from the physics engine didn't actually leave
+            .filter(|&(entity, _)| self.bodies.contains_key(&entity))
+            .collect();
+        left.sort();
+
+        self.fluid_events.extend(left.into_iter()
+            .map(|(entity, fluid)| FluidEvent::Left {fluid, entity}));
+        self.fluid_events.extend(entered.into_iter()
+            .map(|(entity, fluid)| FluidEvent::Entered {fluid, entity}));
+        self.submerged = submerged;
+
+        accelerations
+    }
+
+    /// Returns the outline of every fluid region
+    pub(super) fn fluid_outlines(&self) -> Vec<DebugOutline> {
+        self.fluids.iter().map(|fluid| {
+            let (half_width, half_height) = (fluid.width / 2.0, fluid.height / 2.0);
+            let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+            DebugOutline {
+                points: corners.iter()
+                    .map(|&(x, y)| Point2::new(fluid.center.x + x * half_width, fluid.center.y + y * half_height))
+                    .collect(),
+                closed: true,
+            }
+        }).collect()
+    }
+}
+
+/// Returns the fraction (from 0.0 to 1.0) of the area of the given box that is inside the fluid
+fn submerged_fraction(center: Vec2D, half_size: Vec2D, fluid: &FluidRegion) -> f64 {
+    let overlap = |center: f64, half: f64, fluid_center: f64, fluid_half: f64| {
+        let low = (center - half).max(fluid_center - fluid_half);
+        let high = (center + half).min(fluid_center + fluid_half);
+        (high - low).max(0.0)
+    };
+
+    let overlap_x = overlap(center.x, half_size.x, fluid.center.x, fluid.width / 2.0);
+    let overlap_y = overlap(center.y, half_size.y, fluid.center.y, fluid.height / 2.0);
+    let area = 4.0 * half_size.x * half_size.y;
+    if area <= 0.0 {
+        return 0.0;
+    }
+    overlap_x * overlap_y / area
+}
@@ -0,0 +1,152 @@
+use specs::Entity;
+use nalgebra::Isometry2;
+use nphysics2d::object::{BodyHandle, ColliderHandle};
+use ncollide2d::{
+    query::Proximity,
+    world::CollisionGroups,
+};
+
+use resources::OverlapEvent;
+use map::{CollisionRules, LayerInteraction};
+
+use super::{Physics, Body};
+
+/// The collision group of the overlap sensors of the first layer. Groups below this are the
+/// bodies of each layer, so each layer has two groups.
+const OVERLAP_GROUP_OFFSET: usize = CollisionRules::MAX_LAYERS;
+/// The collision group that every layer collides with. Static boundaries and entities without a
+/// layer are in every group (including this one), so this makes sure that they collide with
+/// bodies on layers that ignore or only overlap with every other layer.
+const ANY_GROUP: usize = 2 * CollisionRules::MAX_LAYERS;
+
+impl Physics {
+    /// Puts the body and direction sensors of the given entity on the given layer. If that layer
+    /// overlaps with any others, a sensor covering the body is added to detect those overlaps.
+    /// Must be called once the body and its sensors have been added to the physics engine.
+    pub(super) fn set_collision_layer(&mut self, entity: Entity, layer: usize) {
+        let (body_handle, collider_handle, is_static) = match self.bodies[&entity] {
+            Body::RigidBody {body_handle, collider_handle} => (body_handle, collider_handle, false),
+            Body::StaticCollider(collider_handle) => (BodyHandle::ground(), collider_handle, true),
+        };
+        self.layers.insert(entity, layer);
+
+        let groups = body_groups(&self.collision_rules, layer);
+        let sensors = self.entity_sensors.get(&entity).cloned().unwrap_or_default();
+        {
+            let collision_world = self.world.collision_world_mut();
+            collision_world.set_collision_groups(collider_handle, groups);
+            // Direction sensors only touch what the body would collide with
+            for sensor in sensors {
+                collision_world.set_collision_groups(sensor, groups);
+            }
+        }
+
+        let overlap_groups = match overlap_sensor_groups(&self.collision_rules, layer) {
+            Some(groups) => groups,
+            None => return,
+        };
+        let (shape, position) = {
+            let collider = self.world.collider(collider_handle)
+                .expect("Collider handle did not map to a collider");
+            // Static colliders are attached to the ground, so their position is relative to the
+            // world instead of a body
+            let position = if is_static {
+                *collider.position()
+            }
+            else {
+                Isometry2::identity()
+            };
+            (collider.shape().clone(), position)
+        };
+        let sensor = self.world.add_sensor(shape, body_handle, position);
+        self.world.collision_world_mut().set_collision_groups(sensor, overlap_groups);
+        self.overlap_sensors.insert(sensor, entity);
+    }
+
+    /// Keeps track of which entities are overlapping based on a proximity event. Does nothing if
+    /// the sensor is not an overlap sensor.
+    pub(super) fn update_overlap(&mut self, sensor: ColliderHandle, other: ColliderHandle, new_status: Proximity) {
+        let entity = match self.overlap_sensors.get(&sensor) {
+            Some(&entity) => entity,
+            None => return,
+        };
+        // Only the bodies of other entities can overlap (not sensors or static boundaries)
+        let other = match self.colliders.get(&other) {
+            Some(&other) if other != entity => other,
+            _ => return,
+        };
+        // Entities without a layer are in every collision group, so they get detected here even
+        // though they collide with everything
+        let overlapping = match (self.layers.get(&entity), self.layers.get(&other)) {
+            (Some(&layer1), Some(&layer2)) => self.collision_rules.interaction(layer1, layer2) == LayerInteraction::Overlap,
+            _ => false,
+        };
+        if !overlapping {
+            return;
+        }
+
+        match new_status {
+            Proximity::Intersecting => {
+                if self.overlaps.insert((entity, other)) {
+                    self.overlap_events.push(OverlapEvent::Started {entity, other});
+                }
+            },
+            Proximity::WithinMargin | Proximity::Disjoint => {
+                if self.overlaps.remove(&(entity, other)) {
+                    self.overlap_events.push(OverlapEvent::Stopped {entity, other});
+                }
+            },
+        }
+    }
+
+    /// Removes the overlap sensor and layer of the given entity. The overlap sensors of rigid
+    /// bodies are removed along with the body, but those of static colliders are not.
+    pub(super) fn remove_collision_layer(&mut self, entity: Entity, static_collider: bool) {
+        self.layers.remove(&entity);
+
+        let sensors: Vec<_> = self.overlap_sensors.iter()
+            .filter(|&(_, &sensor_entity)| sensor_entity == entity)
+            .map(|(&sensor, _)| sensor)
+            .collect();
+        if static_collider {
+            self.world.remove_colliders(&sensors);
+        }
+        for sensor in sensors {
+            self.overlap_sensors.remove(&sensor);
+        }
+    }
+}
+
+/// Returns the collision groups of the bodies on the given layer
+pub(super) fn body_groups(rules: &CollisionRules, layer: usize) -> CollisionGroups {
+    let mut whitelist = vec![ANY_GROUP];
+    for other in 0..rules.layer_count() {
+        match rules.interaction(layer, other) {
+            LayerInteraction::Collide => whitelist.push(other),
+            // Only the overlap sensors of the other layer detect bodies on this layer
+            LayerInteraction::Overlap => whitelist.push(OVERLAP_GROUP_OFFSET + other),
+            LayerInteraction::Ignore => {},
+        }
+    }
+
+    let mut groups = CollisionGroups::new();
+    groups.set_membership(&[layer]);
+    groups.set_whitelist(&whitelist);
+    groups
+}
+
+/// Returns the collision groups of the overlap sensors of the given layer, or None if the layer
+/// doesn't overlap with any other layer
+fn overlap_sensor_groups(rules: &CollisionRules, layer: usize) -> Option<CollisionGroups> {
+    let whitelist: Vec<_> = (0..rules.layer_count())
+        .filter(|&other| rules.interaction(layer, other) == LayerInteraction::Overlap)
+        .collect();
+    if whitelist.is_empty() {
+        return None;
+    }
+
+    let mut groups = CollisionGroups::new();
+    groups.set_membership(&[OVERLAP_GROUP_OFFSET + layer]);
+    groups.set_whitelist(&whitelist);
+    Some(groups)
+}
@@ -0,0 +1,1761 @@
+mod one_way;
+mod projectiles;
+mod fluids;
+mod layers;
+
+use std::{
+    mem,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    f64::consts::PI,
+};
+
+use specs::{
+    Entity,
+    System,
+    SystemData,
+    Join,
+    ReadStorage,
+    WriteStorage,
+    Read,
+    Write,
+    Entities,
+    Resources,
+    ReaderId,
+    InsertedFlag,
+    ModifiedFlag,
+    RemovedFlag,
+    BitSet,
+};
+use nalgebra::{self as na, Isometry2, Point2};
+use nphysics2d::{
+    solver::SignoriniCoulombPyramidModel,
+    algebra::Velocity2,
+    object::{BodyHandle, BodyStatus, Collider, ColliderHandle, Material},
+    force_generator::ConstantAcceleration,
+    volumetric::Volumetric,
+    world::World,
+};
+use shrev::EventChannel;
+use ncollide2d::{
+    bounding_volume::BoundingVolume,
+    events::{ContactEvent, ProximityEvent},
+    query::Proximity,
+    shape::{Cuboid, ConvexPolygon, Polyline, ShapeHandle, Shape},
+    world::CollisionGroups,
+};
+
+use components::{
+    Position,
+    PreviousPosition,
+    Rotation,
+    LockRotation,
+    Velocity,
+    Collisons,
+    GroundContact,
+    BoundingBox,
+    Density,
+    Kinematic,
+    CollisionLayer,
+    Friction,
+    Restitution,
+    AppliedAcceleration,
+    DropDown,
+    Trigger,
+    TriggerShape,
+    Projectile,
+    Suspended,
+};
+use resources::{
+    CollisionEvent,
+    TriggerEvent,
+    FluidEvent,
+    OverlapEvent,
+    ProjectileHit,
+    PhysicsDebug,
+    DebugOutline,
+    Snapshots,
+    SnapshotRequest,
+};
+use math::Vec2D;
+use map::{LevelMap, StaticBoundary, FluidRegion, CollisionRules};
+use physics_query::{PhysicsQuery, QueryCollider};
+use config::PhysicsConfig;
+
+use self::one_way::{OneWayFilter, PassingColliders, line_height_at};
+pub(in systems) use self::projectiles::move_projectiles;
+
+/// How far (in pixels) below the surface of a one-way boundary the bottom of a body can be while
+/// still being considered on top of it
+const ONE_WAY_TOLERANCE: f64 = 2.0;
+/// Bodies moving upwards faster than this (pixels / second) pass through one-way boundaries
+const ONE_WAY_MIN_UP_SPEED: f64 = 1.0;
+/// How far (in pixels) from a one-way boundary bodies are checked for whether they should pass
+/// through it. Must be further than anything moves in a single frame.
+const ONE_WAY_NEAR_DISTANCE: f64 = 32.0;
+
+#[derive(Debug)]
+enum Body {
+    /// Represents a rigid body in the physics engine. This may be either a dynamic or a kinematic
+    /// rigid body.
+    RigidBody {
+        body_handle: BodyHandle,
+        collider_handle: ColliderHandle,
+    },
+    /// Represents a static collider in the physics engine.
+    StaticCollider(ColliderHandle),
+}
+
+#[derive(SystemData)]
+pub struct PhysicsData<'a> {
+    entities: Entities<'a>,
+    densities: ReadStorage<'a, Density>,
+    kinematics: ReadStorage<'a, Kinematic>,
+    frictions: ReadStorage<'a, Friction>,
+    restitutions: ReadStorage<'a, Restitution>,
+    bounding_boxes: ReadStorage<'a, BoundingBox>,
+    applied_accel: ReadStorage<'a, AppliedAcceleration>,
+    triggers: ReadStorage<'a, Trigger>,
+    collision_layers: ReadStorage<'a, CollisionLayer>,
+    positions: WriteStorage<'a, Position>,
+    previous_positions: WriteStorage<'a, PreviousPosition>,
+    rotations: WriteStorage<'a, Rotation>,
+    lock_rotations: ReadStorage<'a, LockRotation>,
+    suspended: ReadStorage<'a, Suspended>,
+    velocities: WriteStorage<'a, Velocity>,
+    collisions: WriteStorage<'a, Collisons>,
+    ground_contacts: WriteStorage<'a, GroundContact>,
+    drop_downs: WriteStorage<'a, DropDown>,
+    projectiles: WriteStorage<'a, Projectile>,
+    collision_events: Write<'a, EventChannel<CollisionEvent>>,
+    trigger_events: Write<'a, EventChannel<TriggerEvent>>,
+    fluid_events: Write<'a, EventChannel<FluidEvent>>,
+    overlap_events: Write<'a, EventChannel<OverlapEvent>>,
+    projectile_hits: Write<'a, EventChannel<ProjectileHit>>,
+    debug: Write<'a, PhysicsDebug>,
+    snapshots: Write<'a, Snapshots>,
+    query: Write<'a, PhysicsQuery>,
+    config: Read<'a, PhysicsConfig>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SensorDirection {
+    Top,
+    Left,
+    Right,
+    Bottom,
+}
+
+/// The state of the physics engine at a single point in time. Used along with the components of
+/// each entity to save and restore the entire simulation.
+#[derive(Debug, Clone)]
+pub struct PhysicsSnapshot {
+    /// The exact position and velocity of every rigid body
+    bodies: HashMap<Entity, (Isometry2<f64>, Velocity2<f64>)>,
+    riders: HashMap<Entity, Entity>,
+    dropping: HashMap<Entity, HashSet<ColliderHandle>>,
+    trigger_contents: HashMap<Entity, HashSet<Entity>>,
+    submerged: HashSet<(Entity, usize)>,
+    overlaps: HashSet<(Entity, Entity)>,
+    /// Every pair of things that were touching, in both orders when both are entities
+    contacts: HashSet<(Entity, Option<Entity>)>,
+}
+
+/// Used to find out which entities have changed since the last frame
+struct ChangeReaders {
+    bounding_box_removed: ReaderId<RemovedFlag>,
+    bounding_box_modified: ReaderId<ModifiedFlag>,
+    density_inserted: ReaderId<InsertedFlag>,
+    density_modified: ReaderId<ModifiedFlag>,
+    density_removed: ReaderId<RemovedFlag>,
+    collision_layer_inserted: ReaderId<InsertedFlag>,
+    collision_layer_modified: ReaderId<ModifiedFlag>,
+    collision_layer_removed: ReaderId<RemovedFlag>,
+}
+
+/// Everything about an entity in the physics engine that needs to be kept while its body is
+/// rebuilt
+struct RebuiltBody {
+    entity: Entity,
+    /// The exact position of the body before it was rebuilt, if it was a rigid body
+    position: Option<Isometry2<f64>>,
+    riders: Vec<(Entity, Entity)>,
+    dropping: Option<HashSet<ColliderHandle>>,
+    /// The triggers that the entity was in
+    triggers: Vec<Entity>,
+    /// The pairs of entities that the entity was overlapping with
+    overlaps: Vec<(Entity, Entity)>,
+}
+
+pub struct Physics {
+    world: World<f64>,
+    /// A copy of the PhysicsConfig resource from the start of the current frame
+    config: PhysicsConfig,
+    /// Needed to rebuild the physics engine when a snapshot is restored
+    static_boundaries: Vec<StaticBoundary>,
+    /// Lookup table for entities kept in the physics engine. Needed for keeping track of which
+    /// entities have been added and which have not beed added.
+    bodies: HashMap<Entity, Body>,
+    /// Lookup table for entities based on the sensor ColliderHandle. Needed for when sensor
+    /// collisions are detected.
+    sensors: HashMap<ColliderHandle, (Entity, SensorDirection)>,
+    /// The direction sensors attached to the body of each entity
+    entity_sensors: HashMap<Entity, Vec<ColliderHandle>>,
+    /// Lookup table for entities based on the ColliderHandle of their body. Needed to find out
+    /// what a sensor is touching.
+    colliders: HashMap<ColliderHandle, Entity>,
+    /// Maps each entity standing on a kinematic body to that kinematic body. Anything standing on
+    /// a kinematic body is carried along with it.
+    riders: HashMap<Entity, Entity>,
+    /// Lookup table for trigger entities based on the ColliderHandle of their sensor
+    trigger_sensors: HashMap<ColliderHandle, Entity>,
+    /// The entities currently inside each trigger, by trigger entity
+    trigger_contents: HashMap<Entity, HashSet<Entity>>,
+    /// Trigger events from the current frame that have not been published yet
+    trigger_events: Vec<TriggerEvent>,
+    /// Every fluid in the map
+    fluids: Vec<FluidRegion>,
+    /// Each entity that is at least partly in a fluid, along with the index of that fluid
+    submerged: HashSet<(Entity, usize)>,
+    /// Fluid events from the current frame that have not been published yet
+    fluid_events: Vec<FluidEvent>,
+    /// Which collision layers collide, only overlap or ignore each other
+    collision_rules: CollisionRules,
+    /// The collision layer of every entity in the physics engine that has one
+    layers: HashMap<Entity, usize>,
+    /// Lookup table for entities based on the ColliderHandle of the sensors that detect when they
+    /// overlap with something
+    overlap_sensors: HashMap<ColliderHandle, Entity>,
+    /// Each entity that is overlapping with something, along with what it is overlapping with
+    overlaps: HashSet<(Entity, Entity)>,
+    /// Overlap events from the current frame that have not been published yet
+    overlap_events: Vec<OverlapEvent>,
+    /// The points of every one-way static boundary, by collider
+    one_way_boundaries: HashMap<ColliderHandle, Vec<Point2<f64>>>,
+    /// Shared with the OneWayFilter in the physics engine so that contacts are ignored between
+    /// each body (and its sensors) and the one-way boundaries it should currently pass through
+    passing: PassingColliders,
+    /// The one-way boundaries that each entity has chosen to drop down through
+    dropping: HashMap<Entity, HashSet<ColliderHandle>>,
+    /// Scratch space for each entity that is close to a one-way boundary, along with that boundary
+    near_one_way: HashSet<(Entity, ColliderHandle)>,
+    /// Scratch space for the pairs of colliders that should pass through each other after this
+    /// frame. Swapped with the pairs in the OneWayFilter once it is ready.
+    next_passing: HashSet<(ColliderHandle, ColliderHandle)>,
+    /// Scratch space for the colliders that started or stopped passing through something
+    changed_passing: HashSet<ColliderHandle>,
+    /// Used to find out when an entity should be removed from or rebuilt in the physics engine.
+    /// Set in setup().
+    change_readers: Option<ChangeReaders>,
+    /// Scratch space for the indexes of entities whose BoundingBox was removed this frame
+    removed: BitSet,
+    /// Scratch space for the indexes of entities whose BoundingBox, Density or CollisionLayer
+    /// changed this frame
+    changed: BitSet,
+    /// A snapshot to apply once every entity has been added back to the rebuilt physics engine
+    pending_restore: Option<PhysicsSnapshot>,
+    /// The pairs of things that were already touching when the snapshot being restored was
+    /// taken. No collision events are started for these during the first step after restoring.
+    restored_contacts: HashSet<(Entity, Option<Entity>)>,
+}
+
+impl Physics {
+    /// Friction of rigid bodies without a Friction component
+    pub const DEFAULT_RIGID_BODY_FRICTION: f64 = 0.0;
+    /// Friction of static colliders without a Friction component or friction property in the map
+    pub const DEFAULT_STATIC_FRICTION: f64 = 0.5;
+    /// Restitution of anything without a Restitution component or bounciness property in the map
+    pub const DEFAULT_RESTITUTION: f64 = 0.0;
+    /// The steepest ground (radians from horizontal) that can be walked on without sliding down
+    pub const MAX_WALKABLE_SLOPE: f64 = PI / 4.0;
+    /// Extra acceleration into walkable ground that keeps bodies from bouncing off of it when
+    /// moving down slopes
+    pub const GROUND_STICK_ACCEL: f64 = 100.0;
+
+    pub fn new(fps: f64, map: &LevelMap, config: PhysicsConfig) -> Self {
+        Self::with_level(
+            fps,
+            map.static_boundaries().to_vec(),
+            map.fluids().to_vec(),
+            map.collision_rules().clone(),
+            config,
+        )
+    }
+
+    fn with_level(
+        fps: f64,
+        static_boundaries: Vec<StaticBoundary>,
+        fluids: Vec<FluidRegion>,
+        collision_rules: CollisionRules,
+        config: PhysicsConfig,
+    ) -> Self {
+        let passing = PassingColliders::default();
+        let mut physics = Self {
+            world: Self::create_world(1.0/fps, config.gravity_accel, &passing),
+            config,
+            static_boundaries,
+            bodies: Default::default(),
+            sensors: Default::default(),
+            entity_sensors: Default::default(),
+            colliders: Default::default(),
+            riders: Default::default(),
+            trigger_sensors: Default::default(),
+            trigger_contents: Default::default(),
+            trigger_events: Vec::new(),
+            fluids,
+            submerged: Default::default(),
+            fluid_events: Vec::new(),
+            collision_rules,
+            layers: Default::default(),
+            overlap_sensors: Default::default(),
+            overlaps: Default::default(),
+            overlap_events: Vec::new(),
+            one_way_boundaries: Default::default(),
+            passing,
+            dropping: Default::default(),
+            near_one_way: Default::default(),
+            next_passing: Default::default(),
+            changed_passing: Default::default(),
+            change_readers: None,
+            removed: BitSet::new(),
+            changed: BitSet::new(),
+            pending_restore: None,
+            restored_contacts: Default::default(),
+        };
+        physics.add_static_boundaries();
+        physics
+    }
+
+    fn create_world(timestep: f64, gravity_accel: f64, passing: &PassingColliders) -> World<f64> {
+        let mut world = World::new();
+        world.set_contact_model(SignoriniCoulombPyramidModel::new());
+        world.set_gravity(Vec2D::y() * gravity_accel);
+        world.set_timestep(timestep);
+
+        world.collision_world_mut().register_broad_phase_pair_filter(
+            "one_way_filter",
+            OneWayFilter {passing: passing.clone()},
+        );
+        world
+    }
+
+    fn add_static_boundaries(&mut self) {
+        let boundaries = self.static_boundaries.clone();
+        for StaticBoundary {points, friction, restitution, one_way} in boundaries {
+            let collider_handle = self.add_static_polyline(
+                &points,
+                friction.unwrap_or(Self::DEFAULT_STATIC_FRICTION),
+                restitution.unwrap_or(Self::DEFAULT_RESTITUTION),
+            );
+
+            if one_way {
+                self.one_way_boundaries.insert(collider_handle, points);
+            }
+        }
+    }
+
+    /// Returns the current state of the physics engine
+    fn snapshot(&self) -> PhysicsSnapshot {
+        let bodies = self.bodies.iter()
+            .filter_map(|(&entity, body)| match *body {
+                Body::RigidBody {body_handle, ..} => {
+                    let rigid_body = self.world.rigid_body(body_handle)
+                        .expect("Body handle did not map to a rigid body");
+                    Some((entity, (rigid_body.position(), *rigid_body.velocity())))
+                },
+                Body::StaticCollider(_) => None,
+            })
+            .collect();
+
+        let mut contacts = HashSet::new();
+        for (collider1, collider2, manifold) in self.world.collision_world().contact_manifolds() {
+            if manifold.contacts().is_empty() {
+                continue;
+            }
+            if let Some((_, entity1, _, entity2)) = self.contact_entities(collider1.handle(), collider2.handle()) {
+                contacts.insert((entity1, entity2));
+                if let Some(entity2) = entity2 {
+                    contacts.insert((entity2, Some(entity1)));
+                }
+            }
+        }
+
+        PhysicsSnapshot {
+            bodies,
+            riders: self.riders.clone(),
+            dropping: self.dropping.clone(),
+            trigger_contents: self.trigger_contents.clone(),
+            submerged: self.submerged.clone(),
+            overlaps: self.overlaps.clone(),
+            contacts,
+        }
+    }
+
+    /// Throws away the physics engine and starts rebuilding it from the given snapshot. Entities
+    /// are added back in the order of their IDs, so rebuilding from the same snapshot always
+    /// results in exactly the same simulation.
+    fn restore(&mut self, snapshot: PhysicsSnapshot) {
+        self.passing.write().expect("bug: lock on passing colliders was poisoned").clear();
+        self.world = Self::create_world(self.world.timestep(), self.config.gravity_accel, &self.passing);
+
+        self.bodies.clear();
+        self.sensors.clear();
+        self.entity_sensors.clear();
+        self.colliders.clear();
+        self.riders.clear();
+        self.trigger_sensors.clear();
+        self.trigger_contents.clear();
+        self.trigger_events.clear();
+        self.submerged.clear();
+        self.fluid_events.clear();
+        self.layers.clear();
+        self.overlap_sensors.clear();
+        self.overlaps.clear();
+        self.overlap_events.clear();
+        self.one_way_boundaries.clear();
+        self.dropping.clear();
+        self.add_static_boundaries();
+
+        // Every entity gets added back during the next frame
+        self.pending_restore = Some(snapshot);
+    }
+
+    /// Puts every entity that was just added back to the physics engine exactly where it was
+    /// when the snapshot was taken
+    fn apply_snapshot(&mut self, snapshot: PhysicsSnapshot) {
+        let PhysicsSnapshot {bodies, riders, dropping, trigger_contents, submerged, overlaps, contacts} = snapshot;
+
+        for (entity, (position, velocity)) in bodies {
+            if let Some(&Body::RigidBody {body_handle, ..}) = self.bodies.get(&entity) {
+                let rigid_body = self.world.rigid_body_mut(body_handle)
+                    .expect("Body handle did not map to a rigid body");
+                rigid_body.set_position(position);
+                rigid_body.set_velocity(velocity);
+            }
+        }
+
+        // Anything deleted since the snapshot was taken can't be restored
+        let bodies = &self.bodies;
+        self.riders = riders.into_iter()
+            .filter(|&(rider, platform)| bodies.contains_key(&rider) && bodies.contains_key(&platform))
+            .collect();
+        self.dropping = dropping.into_iter()
+            .filter(|&(entity, _)| bodies.contains_key(&entity))
+            .collect();
+        self.submerged = submerged.into_iter()
+            .filter(|&(entity, _)| bodies.contains_key(&entity))
+            .collect();
+        self.overlaps = overlaps.into_iter()
+            .filter(|&(entity, other)| bodies.contains_key(&entity) && bodies.contains_key(&other))
+            .collect();
+        // The rebuilt physics engine doesn't know what was touching, so the first step would
+        // otherwise start a collision for everything that was already touching
+        self.restored_contacts = contacts.into_iter()
+            .filter(|&(entity, other)| bodies.contains_key(&entity) && other.map_or(true, |other| bodies.contains_key(&other)))
+            .collect();
+        for (trigger, contents) in trigger_contents {
+            if let Some(current) = self.trigger_contents.get_mut(&trigger) {
+                *current = contents.into_iter()
+                    .filter(|entity| bodies.contains_key(entity))
+                    .collect();
+            }
+        }
+    }
+
+    fn add_static_rect(&mut self, entity: Entity, center: Vec2D, size: Vec2D, friction: f64, restitution: f64) {
+        let shape = Cuboid::new(size / 2.0 - Vec2D::from_element(self.config.collider_margin));
+        let collider_handle = self.add_static_shape(shape, center, friction, restitution);
+        let body = Body::StaticCollider(collider_handle);
+        self.insert_body(entity, body);
+    }
+
+    fn add_static_polyline(&mut self, points: &[Point2<f64>], friction: f64, restitution: f64) -> ColliderHandle {
+        let shape = Polyline::new(points.to_vec());
+        self.add_static_shape(shape, Vec2D::zeros(), friction, restitution)
+    }
+
+    fn add_static_shape(
+        &mut self,
+        shape: impl Shape<f64>,
+        center: Vec2D,
+        friction: f64,
+        restitution: f64,
+    ) -> ColliderHandle {
+        self.world.add_collider(
+            self.config.collider_margin,
+            ShapeHandle::new(shape),
+            BodyHandle::ground(),
+            Isometry2::new(center, na::zero()),
+            Self::material(friction, restitution),
+        )
+    }
+
+    fn add_rigid_body(
+        &mut self,
+        entity: Entity,
+        position: Isometry2<f64>,
+        size: Vec2D,
+        density: f64,
+        lock_rotation: bool,
+        friction: f64,
+        restitution: f64,
+    ) -> BodyHandle {
+        let geom = ShapeHandle::new(Cuboid::new(size / 2.0 - Vec2D::from_element(self.config.collider_margin)));
+        let mut inertia = geom.inertia(density);
+        if lock_rotation {
+            // A body without any angular inertia can't be rotated
+            inertia.angular = 0.0;
+        }
+        let body_handle = self.world.add_rigid_body(
+            position,
+            inertia,
+            geom.center_of_mass(),
+        );
+        self.world.rigid_body_mut(body_handle)
+            .expect("Body handle did not map to a rigid body")
+            .activation_status_mut()
+            .set_deactivation_threshold(self.config.sleep_threshold);
+
+        let body = Body::RigidBody {
+            body_handle,
+            collider_handle: self.world.add_collider(
+                self.config.collider_margin,
+                geom,
+                body_handle,
+                Isometry2::identity(),
+                Self::material(friction, restitution),
+            ),
+        };
+        self.insert_body(entity, body);
+        body_handle
+    }
+
+    fn material(friction: f64, restitution: f64) -> Material<f64> {
+        assert!(friction >= 0.0 && friction <= 1.0, "Friction must be between 0.0 and 1.0");
+        assert!(restitution >= 0.0 && restitution <= 1.0, "Restitution must be between 0.0 and 1.0");
+
+        Material::new(restitution, friction)
+    }
+
+    fn add_kinematic_body(&mut self, entity: Entity, center: Vec2D, size: Vec2D, friction: f64, restitution: f64) -> BodyHandle {
+        // Kinematic bodies are not affected by forces, so their density doesn't matter
+        let position = Isometry2::new(center, na::zero());
+        let body_handle = self.add_rigid_body(entity, position, size, 1.0, false, friction, restitution);
+        self.world.rigid_body_mut(body_handle)
+            .expect("Body handle did not map to a rigid body")
+            .set_status(BodyStatus::Kinematic);
+        body_handle
+    }
+
+    fn insert_body(&mut self, entity: Entity, body: Body) {
+        let collider_handle = match body {
+            Body::RigidBody {collider_handle, ..} | Body::StaticCollider(collider_handle) => collider_handle,
+        };
+        self.colliders.insert(collider_handle, entity);
+
+        self.bodies.insert(entity, body)
+            .map(|_| unreachable!("an entity was added to the physics engine more than once"));
+    }
+
+    /// Removes the given entity and any sensors attached to it from the physics engine
+    fn remove_body(&mut self, entity: Entity) {
+        let body = self.bodies.remove(&entity)
+            .expect("bug: attempt to remove an entity that was not in the physics engine");
+        match body {
+            // Removing a body also removes all of the colliders attached to it
+            Body::RigidBody {body_handle, collider_handle} => {
+                self.world.remove_bodies(&[body_handle]);
+                self.colliders.remove(&collider_handle);
+                self.remove_collision_layer(entity, false);
+            },
+            Body::StaticCollider(collider_handle) => {
+                self.world.remove_colliders(&[collider_handle]);
+                self.colliders.remove(&collider_handle);
+                self.remove_collision_layer(entity, true);
+            },
+        }
+
+        for sensor in self.entity_sensors.remove(&entity).unwrap_or_default() {
+            self.sensors.remove(&sensor);
+        }
+        self.riders.retain(|&rider, &mut platform| rider != entity && platform != entity);
+        self.dropping.remove(&entity);
+
+        // The entity can't be in a trigger if it isn't in the physics engine
+        for (&trigger, contents) in &mut self.trigger_contents {
+            if contents.remove(&entity) {
+                self.trigger_events.push(TriggerEvent::Exit {trigger, entity});
+            }
+        }
+
+        let stopped: Vec<_> = self.overlaps.iter()
+            .filter(|&&(overlapping, other)| overlapping == entity || other == entity)
+            .cloned()
+            .collect();
+        for (overlapping, other) in stopped {
+            self.overlaps.remove(&(overlapping, other));
+            self.overlap_events.push(OverlapEvent::Stopped {entity: overlapping, other});
+        }
+    }
+
+    /// Adds a sensor covering the given region of the world that detects when entities go into it
+    fn add_trigger(&mut self, entity: Entity, shape: &TriggerShape) {
+        let (geom, center) = match *shape {
+            TriggerShape::Rect {center, width, height} => {
+                (ShapeHandle::new(Cuboid::new(Vec2D::new(width / 2.0, height / 2.0))), center)
+            },
+            TriggerShape::Polygon(ref points) => {
+                let polygon = ConvexPolygon::try_from_points(points)
+                    .expect("Trigger polygon should have at least 3 points that are not in a line");
+                (ShapeHandle::new(polygon), Vec2D::zeros())
+            },
+        };
+
+        let sensor = self.world.add_sensor(
+            geom,
+            BodyHandle::ground(),
+            Isometry2::new(center, na::zero()),
+        );
+        self.trigger_sensors.insert(sensor, entity);
+        self.trigger_contents.insert(entity, HashSet::new())
+            .map(|_| unreachable!("a trigger was added to the physics engine more than once"));
+    }
+
+    /// Removes the sensor of the given trigger from the physics engine
+    fn remove_trigger(&mut self, trigger: Entity) {
+        let sensors: Vec<_> = self.trigger_sensors.iter()
+            .filter(|&(_, &sensor_entity)| sensor_entity == trigger)
+            .map(|(&sensor, _)| sensor)
+            .collect();
+        self.world.remove_colliders(&sensors);
+        for sensor in sensors {
+            self.trigger_sensors.remove(&sensor);
+        }
+
+        let contents = self.trigger_contents.remove(&trigger)
+            .expect("bug: attempt to remove a trigger that was not in the physics engine");
+        for entity in contents {
+            self.trigger_events.push(TriggerEvent::Exit {trigger, entity});
+        }
+    }
+
+    /// Records that an entity started or stopped intersecting a trigger
+    fn update_trigger(&mut self, sensor: ColliderHandle, other: ColliderHandle, new_status: Proximity) {
+        let trigger = match self.trigger_sensors.get(&sensor) {
+            Some(&trigger) => trigger,
+            None => return,
+        };
+        // Only the bodies of entities can be in a trigger (not sensors or static boundaries)
+        let entity = match self.colliders.get(&other) {
+            Some(&entity) => entity,
+            None => return,
+        };
+
+        let contents = self.trigger_contents.get_mut(&trigger)
+            .expect("bug: trigger sensor should have had its contents tracked");
+        match new_status {
+            Proximity::Intersecting => {
+                if contents.insert(entity) {
+                    self.trigger_events.push(TriggerEvent::Enter {trigger, entity});
+                }
+            },
+            Proximity::WithinMargin | Proximity::Disjoint => {
+                if contents.remove(&entity) {
+                    self.trigger_events.push(TriggerEvent::Exit {trigger, entity});
+                }
+            },
+        }
+    }
+
+    /// Removes the given entity from the physics engine so that it can be added back with a new
+    /// body. Unlike remove_body(), the entity is not considered to have left any triggers.
+    fn take_for_rebuild(&mut self, entity: Entity) -> RebuiltBody {
+        let position = match self.bodies[&entity] {
+            Body::RigidBody {body_handle, ..} => Some(self.world.rigid_body(body_handle)
+                .expect("Body handle did not map to a rigid body")
+                .position()),
+            Body::StaticCollider(_) => None,
+        };
+        let riders = self.riders.iter()
+            .filter(|&(&rider, &platform)| rider == entity || platform == entity)
+            .map(|(&rider, &platform)| (rider, platform))
+            .collect();
+        let dropping = self.dropping.get(&entity).cloned();
+
+        let mut triggers = Vec::new();
+        for (&trigger, contents) in &mut self.trigger_contents {
+            if contents.remove(&entity) {
+                triggers.push(trigger);
+            }
+        }
+        let overlaps: Vec<_> = self.overlaps.iter()
+            .filter(|&&(overlapping, other)| overlapping == entity || other == entity)
+            .cloned()
+            .collect();
+        for pair in &overlaps {
+            self.overlaps.remove(pair);
+        }
+
+        self.remove_body(entity);
+
+        RebuiltBody {entity, position, riders, dropping, triggers, overlaps}
+    }
+
+    /// Restores everything that was kept about an entity once it has been added back to the
+    /// physics engine
+    fn finish_rebuild(&mut self, rebuilt: RebuiltBody) {
+        let RebuiltBody {entity, position, riders, dropping, triggers, overlaps} = rebuilt;
+
+        match (self.bodies.get(&entity), position) {
+            (Some(&Body::RigidBody {body_handle, ..}), Some(position)) => {
+                // Velocity is kept since it is set from the Velocity component when the body
+                // is added
+                self.world.rigid_body_mut(body_handle)
+                    .expect("Body handle did not map to a rigid body")
+                    .set_position(position);
+            },
+            // Either the entity is no longer in the physics engine or it was not moving before
+            _ => {},
+        }
+
+        for (rider, platform) in riders {
+            if self.bodies.contains_key(&rider) && self.bodies.contains_key(&platform) {
+                self.riders.insert(rider, platform);
+            }
+        }
+        if let Some(dropping) = dropping {
+            self.dropping.insert(entity, dropping);
+        }
+        // If the entity is no longer in a trigger, it will exit on the next step
+        for trigger in triggers {
+            if let Some(contents) = self.trigger_contents.get_mut(&trigger) {
+                contents.insert(entity);
+            }
+        }
+        // Likewise, the entity stops overlapping on the next step if it no longer overlaps
+        for (overlapping, other) in overlaps {
+            if self.bodies.contains_key(&overlapping) && self.bodies.contains_key(&other) {
+                self.overlaps.insert((overlapping, other));
+            }
+        }
+    }
+
+    /// Decides which bodies should currently pass through each one-way boundary. Bodies pass
+    /// through one-way boundaries when they are moving upwards, when they are below the boundary,
+    /// or when they have chosen to drop down through it.
+    fn update_one_way_passing(
+        &mut self,
+        bounding_boxes: &ReadStorage<BoundingBox>,
+        drop_downs: &mut WriteStorage<DropDown>,
+    ) {
+        // Only bodies that are close enough to reach a one-way boundary during the next step
+        // need to be checked. The broad phase finds them without going through every body.
+        self.near_one_way.clear();
+        for &boundary in self.one_way_boundaries.keys() {
+            let aabb = match self.world.collider(boundary) {
+                Some(collider) => collider.shape().aabb(collider.position()).loosened(ONE_WAY_NEAR_DISTANCE),
+                None => continue,
+            };
+            for object in self.world.collision_world().interferences_with_aabb(&aabb, &CollisionGroups::new()) {
+                // Only the bodies of entities can pass through (their sensors are added below)
+                if let Some(&entity) = self.colliders.get(&object.handle()) {
+                    self.near_one_way.insert((entity, boundary));
+                }
+            }
+        }
+
+        self.next_passing.clear();
+        for &(entity, boundary) in &self.near_one_way {
+            let (body_handle, collider_handle) = match self.bodies[&entity] {
+                Body::RigidBody {body_handle, collider_handle} => (body_handle, collider_handle),
+                Body::StaticCollider(_) => continue,
+            };
+            let rigid_body = self.world.rigid_body(body_handle)
+                .expect("Body handle did not map to a rigid body");
+            // Kinematic bodies never collide with static boundaries anyway
+            if rigid_body.is_kinematic() {
+                continue;
+            }
+
+            let &BoundingBox {width, height} = bounding_boxes.get(entity)
+                .expect("Body in the physics engine should have a bounding box");
+            let center = rigid_body.position().translation.vector;
+            let half_width = width as f64 / 2.0;
+            let bottom = center.y + height as f64 / 2.0;
+            let moving_up = rigid_body.velocity().linear.y < -ONE_WAY_MIN_UP_SPEED;
+            let drop_down = drop_downs.get(entity).is_some();
+
+            // The highest part of the boundary that is above or below the body
+            let points = &self.one_way_boundaries[&boundary];
+            let surface = [center.x - half_width, center.x, center.x + half_width].iter()
+                .filter_map(|&x| line_height_at(points, x))
+                .fold(None, |highest: Option<f64>, y| Some(highest.map_or(y, |highest| highest.min(y))));
+            let below = match surface {
+                Some(surface) => bottom > surface + ONE_WAY_TOLERANCE,
+                // Not above or below the boundary at all
+                None => true,
+            };
+
+            let dropping = self.dropping.entry(entity).or_insert_with(HashSet::new);
+            if below {
+                // Passing through normally from now on
+                dropping.remove(&boundary);
+            }
+            // Only drop through the boundary being stood on, not every one further down
+            else if drop_down && surface.map_or(false, |surface| (bottom - surface).abs() <= ONE_WAY_TOLERANCE) {
+                dropping.insert(boundary);
+            }
+
+            if below || moving_up || dropping.contains(&boundary) {
+                self.next_passing.insert((collider_handle, boundary));
+                // The sensors need to pass through too so that the entity isn't considered
+                // to be touching the boundary
+                for &sensor in self.entity_sensors.get(&entity).into_iter().flat_map(|sensors| sensors) {
+                    self.next_passing.insert((sensor, boundary));
+                }
+            }
+        }
+
+        // Anything that moved away from a boundary can't still be dropping through it
+        let near_one_way = &self.near_one_way;
+        for (&entity, dropping) in &mut self.dropping {
+            dropping.retain(|&boundary| near_one_way.contains(&(entity, boundary)));
+        }
+        // Dropping down only needs to be requested once
+        for _ in drop_downs.drain().join() {}
+
+        self.changed_passing.clear();
+        {
+            let mut current = self.passing.write().expect("bug: lock on passing colliders was poisoned");
+            self.changed_passing.extend(current.symmetric_difference(&self.next_passing)
+                .map(|&(collider, _)| collider));
+            // Swapped so that the allocation of the old pairs is reused next frame
+            mem::swap(&mut *current, &mut self.next_passing);
+        }
+
+        // The filter only gets checked again for a collider if something about it changes
+        for &collider_handle in &self.changed_passing {
+            let groups = match self.world.collider(collider_handle) {
+                Some(collider) => *collider.collision_groups(),
+                // Collider was removed from the physics engine
+                None => continue,
+            };
+            self.world.collision_world_mut().set_collision_groups(collider_handle, groups);
+        }
+    }
+
+    /// Converts the contact events from the last step into collision events between entities
+    fn collision_events(&self, pre_step_velocities: &HashMap<BodyHandle, Vec2D>) -> Vec<CollisionEvent> {
+        self.world.contact_events().iter().filter_map(|event| match *event {
+            ContactEvent::Started(collider1, collider2) => {
+                self.contact_entities(collider1, collider2)
+                    .filter(|&(_, entity1, _, entity2)| !self.restored_contacts.contains(&(entity1, entity2)))
+                    .map(|(collider1, entity1, collider2, entity2)| {
+                        let normal = self.contact_normal(collider1, collider2).unwrap_or_else(Vec2D::zeros);
+                        let impulse = self.impact_impulse(collider1, collider2, normal, pre_step_velocities);
+                        CollisionEvent::Started {entity1, entity2, normal, impulse}
+                    })
+            },
+            ContactEvent::Stopped(collider1, collider2) => {
+                self.contact_entities(collider1, collider2).map(|(_, entity1, _, entity2)| {
+                    CollisionEvent::Stopped {entity1, entity2}
+                })
+            },
+        }).collect()
+    }
+
+    /// Returns the entities of two colliders that are touching, ordered so that the first one
+    /// always belongs to an entity. Returns None if neither collider belongs to an entity (or the
+    /// entities have been removed).
+    fn contact_entities(
+        &self,
+        collider1: ColliderHandle,
+        collider2: ColliderHandle,
+    ) -> Option<(ColliderHandle, Entity, ColliderHandle, Option<Entity>)> {
+        match (self.colliders.get(&collider1), self.colliders.get(&collider2)) {
+            (Some(&entity1), entity2) => Some((collider1, entity1, collider2, entity2.cloned())),
+            (None, Some(&entity2)) => Some((collider2, entity2, collider1, None)),
+            (None, None) => None,
+        }
+    }
+
+    /// Returns the normal of the deepest contact between two colliders, pointing from the first
+    /// collider towards the second
+    fn contact_normal(&self, collider1: ColliderHandle, collider2: ColliderHandle) -> Option<Vec2D> {
+        let contact_pair = self.world.collision_world().contact_pair(collider1, collider2)?;
+        let mut manifolds = Vec::new();
+        contact_pair.contacts(&mut manifolds);
+        let normal = manifolds.iter()
+            .filter_map(|manifold| manifold.deepest_contact())
+            .max_by(|c1, c2| c1.contact.depth.partial_cmp(&c2.contact.depth).unwrap_or(Ordering::Equal))
+            .map(|tracked| *tracked.contact.normal)?;
+
+        // Contacts are always computed with the lower collider handle first
+        if collider1 < collider2 {
+            Some(normal)
+        }
+        else {
+            Some(-normal)
+        }
+    }
+
+    /// Returns the flattest ground that each entity with a rigid body is touching. Ground is
+    /// anything touching the entity from below.
+    fn ground_contacts(&self) -> HashMap<Entity, GroundContact> {
+        let mut grounds: HashMap<Entity, GroundContact> = HashMap::new();
+        for (collider1, collider2, manifold) in self.world.collision_world().contact_manifolds() {
+            // Points from the first collider towards the second
+            let normal = match manifold.deepest_contact() {
+                Some(tracked) => *tracked.contact.normal,
+                None => continue,
+            };
+
+            let pairs = [
+                (collider2.handle(), collider1.handle(), normal),
+                (collider1.handle(), collider2.handle(), -normal),
+            ];
+            for &(collider, ground, normal) in &pairs {
+                let entity = match self.colliders.get(&collider) {
+                    Some(&entity) => entity,
+                    None => continue,
+                };
+                // Positive y is downwards, so only normals pointing up come from the ground
+                if normal.y >= 0.0 {
+                    continue;
+                }
+
+                let flatter = grounds.get(&entity).map_or(true, |current| normal.y < current.normal.y);
+                if flatter {
+                    let ground_entity = self.colliders.get(&ground).cloned();
+                    grounds.insert(entity, GroundContact::new(normal, ground_entity, Self::MAX_WALKABLE_SLOPE));
+                }
+            }
+        }
+        grounds
+    }
+
+    /// Adjusts the acceleration applied to a body standing on walkable ground so that horizontal
+    /// acceleration moves it along the slope (at the same rate uphill and downhill), it does not
+    /// slide down the slope when standing still and it stays on the ground when moving down it.
+    fn ground_accel(accel: Vec2D, ground: &GroundContact, gravity_accel: f64) -> Vec2D {
+        // Jumping or anything else pushing the body up still leaves the ground
+        if accel.y < 0.0 {
+            return accel;
+        }
+
+        // Along the surface of the ground, pointing right
+        let tangent = Vec2D::new(-ground.normal.y, ground.normal.x);
+        let gravity = Vec2D::y() * gravity_accel;
+        tangent * accel.x
+            - tangent * gravity.dot(&tangent)
+            - ground.normal * Self::GROUND_STICK_ACCEL
+            + Vec2D::y() * accel.y
+    }
+
+    /// Estimates the impulse along the normal needed to stop two colliders from moving into each
+    /// other based on their velocities before they touched
+    fn impact_impulse(
+        &self,
+        collider1: ColliderHandle,
+        collider2: ColliderHandle,
+        normal: Vec2D,
+        pre_step_velocities: &HashMap<BodyHandle, Vec2D>,
+    ) -> f64 {
+        // Returns the velocity and mass of the body of a collider. Static and kinematic bodies
+        // have infinite mass since they can't be pushed.
+        let body_motion = |collider: ColliderHandle| {
+            let body_handle = self.world.collider(collider)
+                .expect("Collider from a contact event should exist")
+                .data()
+                .body();
+            let velocity = pre_step_velocities.get(&body_handle).cloned().unwrap_or_else(Vec2D::zeros);
+            let mass = match self.world.rigid_body(body_handle) {
+                Some(body) if body.is_dynamic() => body.local_inertia().linear,
+                _ => ::std::f64::INFINITY,
+            };
+            (velocity, mass)
+        };
+        let (velocity1, mass1) = body_motion(collider1);
+        let (velocity2, mass2) = body_motion(collider2);
+
+        let effective_mass = match (mass1.is_finite(), mass2.is_finite()) {
+            (true, true) => mass1 * mass2 / (mass1 + mass2),
+            (true, false) => mass1,
+            (false, true) => mass2,
+            (false, false) => return 0.0,
+        };
+
+        // Speed at which the two were moving towards each other along the normal
+        let approach_speed = (velocity1 - velocity2).dot(&normal);
+        effective_mass * approach_speed.max(0.0)
+    }
+
+    /// Replaces the contents of the given debug info with the current state of the physics engine
+    fn update_debug(&self, debug: &mut PhysicsDebug, collisions: &WriteStorage<Collisons>) {
+        debug.colliders.clear();
+        debug.sensors.clear();
+        debug.triggers.clear();
+        debug.velocities.clear();
+        debug.contacts.clear();
+
+        for collider in self.world.colliders() {
+            let outline = match debug_outline(collider) {
+                Some(outline) => outline,
+                None => continue,
+            };
+
+            let handle = collider.handle();
+            if let Some(&(entity, direction)) = self.sensors.get(&handle) {
+                let touching = collisions.get(entity).map_or(false, |collisions| match direction {
+                    SensorDirection::Top => collisions.top,
+                    SensorDirection::Left => collisions.left,
+                    SensorDirection::Right => collisions.right,
+                    SensorDirection::Bottom => collisions.bottom,
+                });
+                debug.sensors.push((outline, touching));
+            }
+            else if self.trigger_sensors.contains_key(&handle) {
+                debug.triggers.push(outline);
+            }
+            else if self.overlap_sensors.contains_key(&handle) {
+                // Always the same as the outline of the body
+                continue;
+            }
+            else {
+                debug.colliders.push(outline);
+            }
+        }
+
+        debug.fluids = self.fluid_outlines();
+
+        for body in self.bodies.values() {
+            if let Body::RigidBody {body_handle, ..} = *body {
+                let rigid_body = self.world.rigid_body(body_handle)
+                    .expect("Body handle did not map to a rigid body");
+                let center = Point2::from_coordinates(rigid_body.position().translation.vector);
+                debug.velocities.push((center, rigid_body.velocity().linear));
+            }
+        }
+
+        for (_, _, manifold) in self.world.collision_world().contact_manifolds() {
+            debug.contacts.extend(manifold.contacts().iter().map(|tracked| tracked.contact.world1));
+        }
+    }
+
+    /// Returns every collider that can be found by a PhysicsQuery
+    fn query_colliders(&self) -> Vec<QueryCollider> {
+        self.world.colliders()
+            .filter(|collider| {
+                let handle = collider.handle();
+                !self.sensors.contains_key(&handle) && !self.trigger_sensors.contains_key(&handle)
+                    && !self.overlap_sensors.contains_key(&handle)
+            })
+            .map(|collider| QueryCollider {
+                shape: collider.shape().clone(),
+                position: *collider.position(),
+                entity: self.colliders.get(&collider.handle()).cloned(),
+                one_way: self.one_way_boundaries.contains_key(&collider.handle()),
+            })
+            .collect()
+    }
+
+    /// Adds a sensor to the given body and registers that it results in the given entity touching
+    /// something in the given direction
+    fn insert_sensor(
+        &mut self,
+        entity: Entity,
+        body_handle: BodyHandle,
+        direction: SensorDirection,
+        offset_x: f64,
+        offset_y: f64,
+        width: f64,
+        height: f64,
+    ) {
+        let sensor_geom = ShapeHandle::new(Cuboid::new(Vec2D::new(
+            width / 2.0,
+            height / 2.0,
+        )));
+        let collider_handle = self.world.add_sensor(
+            sensor_geom,
+            body_handle,
+            Isometry2::new(Vec2D::new(offset_x, offset_y), na::zero()),
+        );
+        self.sensors.insert(collider_handle, (entity, direction))
+            .map(|_| unreachable!("collider handle should have been unique in the physics engine"));
+        self.entity_sensors.entry(entity).or_insert_with(Vec::new).push(collider_handle);
+    }
+}
+
+impl<'a> System<'a> for Physics {
+    type SystemData = PhysicsData<'a>;
+
+    fn setup(&mut self, res: &mut Resources) {
+        <Self::SystemData as SystemData>::setup(res);
+
+        let mut bounding_boxes = WriteStorage::<BoundingBox>::fetch(res);
+        let mut densities = WriteStorage::<Density>::fetch(res);
+        let mut collision_layers = WriteStorage::<CollisionLayer>::fetch(res);
+        self.change_readers = Some(ChangeReaders {
+            bounding_box_removed: bounding_boxes.track_removed(),
+            bounding_box_modified: bounding_boxes.track_modified(),
+            density_inserted: densities.track_inserted(),
+            density_modified: densities.track_modified(),
+            density_removed: densities.track_removed(),
+            collision_layer_inserted: collision_layers.track_inserted(),
+            collision_layer_modified: collision_layers.track_modified(),
+            collision_layer_removed: collision_layers.track_removed(),
+        });
+    }
+
+    fn run(&mut self, data: Self::SystemData) {
+        let PhysicsData {
+            entities,
+            densities,
+            kinematics,
+            frictions,
+            restitutions,
+            bounding_boxes,
+            applied_accel,
+            triggers,
+            collision_layers,
+            mut positions,
+            mut previous_positions,
+            mut rotations,
+            lock_rotations,
+            suspended,
+            mut velocities,
+            mut collisions,
+            mut ground_contacts,
+            mut drop_downs,
+            mut projectiles,
+            mut collision_events,
+            mut trigger_events,
+            mut fluid_events,
+            mut overlap_events,
+            mut projectile_hits,
+            mut debug,
+            mut snapshots,
+            mut query,
+            config,
+        } = data;
+        // The config may have been reloaded since the last frame
+        self.config = *config;
+        self.world.set_gravity(Vec2D::y() * self.config.gravity_accel);
+
+        // Entities without a BoundingBox (including deleted entities) can no longer be in the
+        // physics engine. This must happen before adding new bodies since entity indexes get reused.
+        self.removed.clear();
+        self.changed.clear();
+        {
+            let readers = self.change_readers.as_mut()
+                .expect("Physics::setup() was not called before running the system");
+            bounding_boxes.populate_removed(&mut readers.bounding_box_removed, &mut self.removed);
+            bounding_boxes.populate_modified(&mut readers.bounding_box_modified, &mut self.changed);
+            densities.populate_inserted(&mut readers.density_inserted, &mut self.changed);
+            densities.populate_modified(&mut readers.density_modified, &mut self.changed);
+            densities.populate_removed(&mut readers.density_removed, &mut self.changed);
+            collision_layers.populate_inserted(&mut readers.collision_layer_inserted, &mut self.changed);
+            collision_layers.populate_modified(&mut readers.collision_layer_modified, &mut self.changed);
+            collision_layers.populate_removed(&mut readers.collision_layer_removed, &mut self.changed);
+        }
+        let removed_entities: Vec<_> = self.bodies.keys()
+            .filter(|entity| self.removed.contains(entity.id()))
+            .cloned()
+            .collect();
+        for entity in removed_entities {
+            self.remove_body(entity);
+        }
+
+        // Entities whose BoundingBox, Density or CollisionLayer changed are taken out of the
+        // physics engine and added back below with a new collider, mass, sensors and layer
+        let changed_entities: Vec<_> = self.bodies.keys()
+            .filter(|entity| self.changed.contains(entity.id()))
+            .cloned()
+            .collect();
+        let rebuilt_bodies: Vec<_> = changed_entities.into_iter()
+            .map(|entity| self.take_for_rebuild(entity))
+            .collect();
+
+        // Triggers are removed once their Trigger component is removed or they are deleted
+        let removed_triggers: Vec<_> = self.trigger_contents.keys()
+            .filter(|&&trigger| triggers.get(trigger).is_none())
+            .cloned()
+            .collect();
+        for trigger in removed_triggers {
+            self.remove_trigger(trigger);
+        }
+
+        for (entity, trigger) in (&*entities, &triggers).join() {
+            if !self.trigger_contents.contains_key(&entity) {
+                self.add_trigger(entity, &trigger.shape);
+            }
+        }
+
+        for (entity, &BoundingBox {width, height}, &Position(pos)) in (&*entities, &bounding_boxes, &positions).join() {
+            // Check if already added
+            if self.bodies.contains_key(&entity) {
+                continue;
+            }
+
+            let size = Vec2D::new(width as f64, height as f64);
+            let friction = frictions.get(entity).map(|&Friction(friction)| friction);
+            let restitution = restitutions.get(entity).map(|&Restitution(restitution)| restitution)
+                .unwrap_or(Self::DEFAULT_RESTITUTION);
+
+            if kinematics.get(entity).is_some() {
+                self.add_kinematic_body(
+                    entity,
+                    pos,
+                    size,
+                    friction.unwrap_or(Self::DEFAULT_STATIC_FRICTION),
+                    restitution,
+                );
+                continue;
+            }
+
+            // Determine if this is a static body or not
+            let density = densities.get(entity);
+            match density {
+                // Rigid body
+                Some(&Density(density)) => {
+                    let body_handle = self.add_rigid_body(
+                        entity,
+                        Isometry2::new(pos, rotations.get(entity).map_or(0.0, |&Rotation(angle)| angle)),
+                        size,
+                        density,
+                        lock_rotations.get(entity).is_some(),
+                        friction.unwrap_or(Self::DEFAULT_RIGID_BODY_FRICTION),
+                        restitution,
+                    );
+
+                    if let Some(&Velocity(vel)) = velocities.get(entity) {
+                        self.world.rigid_body_mut(body_handle)
+                            .expect("Body handle did not map to a rigid body")
+                            .set_linear_velocity(vel);
+                    }
+
+                    // If this entity will need to detect collisions, attach sensors to it
+                    if let Some(&Collisons {..}) = collisions.get(entity) {
+                        let width = width as f64;
+                        let height = height as f64;
+                        let PhysicsConfig {sensor_size, sensor_scale_factor, ..} = self.config;
+
+                        // Positions each sensor at its position around the entity
+                        self.insert_sensor(
+                            entity,
+                            body_handle,
+                            SensorDirection::Top,
+                            0.0,
+                            -(height / 2.0 - sensor_size / 2.0),
+                            width * sensor_scale_factor,
+                            sensor_size,
+                        );
+                        self.insert_sensor(
+                            entity,
+                            body_handle,
+                            SensorDirection::Bottom,
+                            0.0,
+                            height / 2.0 + sensor_size / 2.0,
+                            width * sensor_scale_factor,
+                            sensor_size,
+                        );
+                        self.insert_sensor(
+                            entity,
+                            body_handle,
+                            SensorDirection::Left,
+                            -(width / 2.0 + sensor_size / 2.0),
+                            0.0,
+                            sensor_size,
+                            height * sensor_scale_factor,
+                        );
+                        self.insert_sensor(
+                            entity,
+                            body_handle,
+                            SensorDirection::Right,
+                            width / 2.0 - sensor_size / 2.0,
+                            0.0,
+                            sensor_size,
+                            height * sensor_scale_factor,
+                        );
+                    }
+                },
+                // Static collider
+                None => {
+                    self.add_static_rect(
+                        entity,
+                        pos,
+                        size,
+                        friction.unwrap_or(Self::DEFAULT_STATIC_FRICTION),
+                        restitution,
+                    );
+                },
+            }
+        }
+
+        // Put everything that was just added on its collision layer
+        for (entity, &CollisionLayer(ref name)) in (&*entities, &collision_layers).join() {
+            if self.bodies.contains_key(&entity) && !self.layers.contains_key(&entity) {
+                let layer = self.collision_rules.layer(name);
+                self.set_collision_layer(entity, layer);
+            }
+        }
+
+        for rebuilt in rebuilt_bodies {
+            self.finish_rebuild(rebuilt);
+        }
+
+        if let Some(snapshot) = self.pending_restore.take() {
+            self.apply_snapshot(snapshot);
+        }
+
+        // Suspended bodies are left out of the simulation entirely until they are resumed
+        for (&entity, body) in &self.bodies {
+            let body_handle = match *body {
+                Body::RigidBody {body_handle, ..} => body_handle,
+                Body::StaticCollider(_) => continue,
+            };
+            let status = if suspended.get(entity).is_some() {
+                BodyStatus::Disabled
+            }
+            else if kinematics.get(entity).is_some() {
+                BodyStatus::Kinematic
+            }
+            else {
+                BodyStatus::Dynamic
+            };
+
+            let rigid_body = self.world.rigid_body_mut(body_handle)
+                .expect("Body handle did not map to a rigid body");
+            if rigid_body.status() != status {
+                rigid_body.set_status(status);
+                rigid_body.activate();
+            }
+        }
+
+        let fluid_accel = self.fluid_accelerations(&bounding_boxes, &densities, &kinematics);
+        fluid_events.drain_vec_write(&mut self.fluid_events);
+
+        // Sleeping bodies ignore forces, so anything that is being pushed needs to be woken up
+        let pushed: Vec<_> = (&*entities, &applied_accel).join()
+            .filter(|&(_, &AppliedAcceleration(accel))| accel != Vec2D::zeros())
+            .map(|(entity, _)| entity)
+            .chain(fluid_accel.keys().cloned())
+            .collect();
+        for entity in pushed {
+            if let Some(&Body::RigidBody {body_handle, ..}) = self.bodies.get(&entity) {
+                self.world.activate_body(body_handle);
+            }
+        }
+
+        // Apply accelerations to every rigid body (if any accelerations have been applied)
+        let gravity_accel = self.config.gravity_accel;
+        let body_accel = self.bodies.iter()
+            .filter_map(|(&entity, body)| {
+                let body_handle = match *body {
+                    Body::RigidBody {body_handle, ..} => body_handle,
+                    Body::StaticCollider(_) => return None,
+                };
+
+                let accel = applied_accel.get(entity).map(|&AppliedAcceleration(accel)| accel);
+                let accel = match ground_contacts.get(entity).filter(|ground| ground.walkable) {
+                    Some(ground) => Some(Self::ground_accel(accel.unwrap_or_else(Vec2D::zeros), ground, gravity_accel)),
+                    None => accel,
+                };
+                match (accel, fluid_accel.get(&entity)) {
+                    (Some(accel), Some(&fluid)) => Some((body_handle, accel + fluid)),
+                    (Some(accel), None) => Some((body_handle, accel)),
+                    (None, Some(&fluid)) => Some((body_handle, fluid)),
+                    (None, None) => None,
+                }
+            });
+
+        let mut force_handles = Vec::new();
+        for (body_handle, accel) in body_accel {
+            let mut generator = ConstantAcceleration::new(accel, 0.0);
+            generator.add_body_part(body_handle);
+            let force_handle = self.world.add_force_generator(generator);
+            force_handles.push(force_handle);
+        }
+
+        // Kinematic bodies move at exactly the velocity they were given
+        for (entity, _, &Velocity(vel)) in (&*entities, &kinematics, &velocities).join() {
+            if let Some(&Body::RigidBody {body_handle, ..}) = self.bodies.get(&entity) {
+                self.world.rigid_body_mut(body_handle)
+                    .expect("Body handle did not map to a rigid body")
+                    .set_linear_velocity(vel);
+            }
+        }
+
+        self.update_one_way_passing(&bounding_boxes, &mut drop_downs);
+
+        // Needed to figure out how hard things hit each other
+        let pre_step_velocities: HashMap<_, _> = self.bodies.values()
+            .filter_map(|body| match *body {
+                Body::RigidBody {body_handle, ..} => Some((body_handle, self.world.rigid_body(body_handle)
+                    .expect("Body handle did not map to a rigid body")
+                    .velocity().linear)),
+                Body::StaticCollider(_) => None,
+            })
+            .collect();
+
+        // Systems are run exactly once per frame, so the world only needs to step once
+        self.world.step();
+
+        let mut events = self.collision_events(&pre_step_velocities);
+        collision_events.drain_vec_write(&mut events);
+        self.restored_contacts.clear();
+
+        for force_handle in force_handles {
+            self.world.remove_force_generator(force_handle);
+        }
+
+        // Entities already in a trigger before this frame are still in it unless they exit below
+        let previous_contents = self.trigger_contents.clone();
+
+        // Handle sensor events to determine which collisions have occurred
+        // Copied so that self can be modified while handling each event
+        let proximity_events: Vec<_> = self.world.proximity_events().iter().cloned().collect();
+        for ProximityEvent {collider1, collider2, new_status, ..} in proximity_events {
+            for &(sensor, other) in &[(collider1, collider2), (collider2, collider1)] {
+                self.update_trigger(sensor, other, new_status);
+                self.update_overlap(sensor, other, new_status);
+
+                // Triggers and overlap sensors don't count as touching anything
+                if self.trigger_sensors.contains_key(&other) || self.overlap_sensors.contains_key(&other) {
+                    continue;
+                }
+
+                if let Some(&(entity, direction)) = self.sensors.get(&sensor) {
+                    let collisions = collisions.get_mut(entity)
+                        .expect("Body with sensors should have a Collisons component");
+                    let status = match new_status {
+                        Proximity::WithinMargin | Proximity::Intersecting => true,
+                        Proximity::Disjoint => false,
+                    };
+                    match direction {
+                        SensorDirection::Top => collisions.top = status,
+                        SensorDirection::Left => collisions.left = status,
+                        SensorDirection::Right => collisions.right = status,
+                        SensorDirection::Bottom => collisions.bottom = status,
+                    }
+
+                    // Keep track of anything that starts or stops standing on a kinematic body
+                    let platform = self.colliders.get(&other).cloned()
+                        .filter(|&platform| kinematics.get(platform).is_some());
+                    if let (SensorDirection::Bottom, Some(platform)) = (direction, platform) {
+                        if status {
+                            self.riders.insert(entity, platform);
+                        }
+                        else if self.riders.get(&entity) == Some(&platform) {
+                            self.riders.remove(&entity);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (&trigger, contents) in &self.trigger_contents {
+            let previous = match previous_contents.get(&trigger) {
+                Some(previous) => previous,
+                // Trigger was added this frame
+                None => continue,
+            };
+            for &entity in contents.intersection(previous) {
+                self.trigger_events.push(TriggerEvent::Stay {trigger, entity});
+            }
+        }
+        trigger_events.drain_vec_write(&mut self.trigger_events);
+        overlap_events.drain_vec_write(&mut self.overlap_events);
+
+        // Keep track of the ground under everything that detects collisions
+        let mut grounds = self.ground_contacts();
+        for (entity, _) in (&*entities, &collisions).join() {
+            match grounds.remove(&entity) {
+                Some(ground) => {
+                    ground_contacts.insert(entity, ground)
+                        .expect("bug: entity with collisions should still be alive");
+                },
+                None => {
+                    ground_contacts.remove(entity);
+                },
+            }
+        }
+
+        // Carry anything standing on a kinematic body along with it
+        let timestep = self.world.timestep();
+        for (&rider, &platform) in &self.riders {
+            let platform_vel = match self.bodies[&platform] {
+                Body::RigidBody {body_handle, ..} => self.world.rigid_body(body_handle)
+                    .expect("Body handle did not map to a rigid body")
+                    .velocity().linear,
+                Body::StaticCollider(_) => unreachable!("bug: kinematic bodies should be rigid bodies"),
+            };
+            // The contact with the platform already pushes the rider up when the platform moves
+            // up, so the rider only needs to be moved down with it
+            let displacement = Vec2D::new(platform_vel.x, platform_vel.y.max(0.0)) * timestep;
+
+            if let Body::RigidBody {body_handle, ..} = self.bodies[&rider] {
+                let rider_body = self.world.rigid_body_mut(body_handle)
+                    .expect("Body handle did not map to a rigid body");
+                rider_body.apply_displacement(&Velocity2::new(displacement, 0.0));
+                // The collider of a sleeping body would be left behind
+                if displacement != Vec2D::zeros() {
+                    rider_body.activate();
+                }
+            }
+        }
+
+        let mut hits = self.move_projectiles(
+            &entities,
+            &mut projectiles,
+            &mut positions,
+            &mut previous_positions,
+            &velocities,
+        );
+        projectile_hits.drain_vec_write(&mut hits);
+
+        if debug.enabled {
+            self.update_debug(&mut debug, &collisions);
+        }
+
+        // Update every tracked entity with the latest values from the physics engine
+        // We don't need to update static colliders because they do not move
+        for (&entity, body) in self.bodies.iter() {
+            if let &Body::RigidBody {body_handle, ..} = body {
+                let Position(position) = positions.get_mut(entity)
+                    .expect("Rigid body should have had a position");
+                previous_positions.insert(entity, PreviousPosition(*position))
+                    .expect("bug: entity in the physics engine should still be alive");
+                let Velocity(velocity) = velocities.get_mut(entity)
+                    .expect("Rigid body should have had a velocity");
+
+                let physics_body = self.world.rigid_body(body_handle)
+                    .expect("Body handle did not map to a rigid body");
+                *position = physics_body.position().translation.vector;
+                if let Some(Rotation(angle)) = rotations.get_mut(entity) {
+                    *angle = physics_body.position().rotation.angle();
+                }
+                *velocity = physics_body.velocity().linear;
+            }
+        }
+
+        query.update(self.query_colliders());
+
+        // The components of each entity are saved and restored separately after this frame
+        match snapshots.request {
+            Some(SnapshotRequest::Save) => {
+                snapshots.physics = Some(self.snapshot());
+            },
+            Some(SnapshotRequest::Restore) => {
+                if let Some(snapshot) = snapshots.physics.clone() {
+                    self.restore(snapshot);
+                }
+            },
+            None => {},
+        }
+    }
+}
+
+/// Returns the outline of the given collider in world coordinates, or None if the collider does
+/// not have a shape that can be outlined
+fn debug_outline(collider: &Collider<f64>) -> Option<DebugOutline> {
+    let position = collider.position();
+    let shape = collider.shape();
+
+    if let Some(cuboid) = shape.as_shape::<Cuboid<f64>>() {
+        let half = cuboid.half_extents();
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        Some(DebugOutline {
+            points: corners.iter()
+                .map(|&(x, y)| position * Point2::new(x * half.x, y * half.y))
+                .collect(),
+            closed: true,
+        })
+    }
+    else if let Some(polygon) = shape.as_shape::<ConvexPolygon<f64>>() {
+        Some(DebugOutline {
+            points: polygon.points().iter().map(|point| position * point).collect(),
+            closed: true,
+        })
+    }
+    else if let Some(polyline) = shape.as_shape::<Polyline<f64>>() {
+        Some(DebugOutline {
+            points: polyline.vertices().iter().map(|point| position * point).collect(),
+            closed: false,
+        })
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use specs::{World, Builder, RunNow};
+
+    const FPS: f64 = 60.0;
+
+    fn setup() -> (Physics, World) {
+        let mut world = World::new();
+        let mut physics = Physics::with_level(FPS, Vec::new(), Vec::new(), CollisionRules::default(), PhysicsConfig::default());
+        System::setup(&mut physics, &mut world.res);
+        (physics, world)
+    }
+
+    /// Creates an entity that is either a static collider, a rigid body or a rigid body with
+    /// sensors depending on the given kind
+    fn spawn(world: &mut World, kind: usize, x: f64) -> Entity {
+        let builder = world.create_entity()
+            .with(Position(Vec2D::new(x, 0.0)))
+            .with(BoundingBox {width: 16, height: 16});
+        match kind % 3 {
+            0 => builder.build(),
+            1 => builder
+                .with(Density(1.0))
+                .with(Velocity(Vec2D::zeros()))
+                .build(),
+            _ => builder
+                .with(Density(1.0))
+                .with(Velocity(Vec2D::zeros()))
+                .with(Collisons::default())
+                .build(),
+        }
+    }
+
+    /// Asserts that every entity in the physics engine is alive and that every handle still
+    /// refers to the body or collider of the entity it is stored with
+    fn assert_in_sync(physics: &Physics, world: &World) {
+        let entities = world.entities();
+        let bounding_boxes = world.read_storage::<BoundingBox>();
+        let densities = world.read_storage::<Density>();
+        let collisions = world.read_storage::<Collisons>();
+
+        for (&entity, body) in &physics.bodies {
+            assert!(entities.is_alive(entity), "deleted entity {:?} is still in the physics engine", entity);
+            match *body {
+                Body::RigidBody {body_handle, collider_handle} => {
+                    assert!(physics.world.rigid_body(body_handle).is_some());
+                    assert_eq!(physics.world.collider_body_handle(collider_handle), Some(body_handle));
+                },
+                Body::StaticCollider(collider_handle) => {
+                    assert_eq!(physics.world.collider_body_handle(collider_handle), Some(BodyHandle::ground()));
+                },
+            }
+        }
+
+        for (&collider_handle, entity) in &physics.colliders {
+            match physics.bodies.get(entity) {
+                Some(&Body::RigidBody {collider_handle: handle, ..}) |
+                Some(&Body::StaticCollider(handle)) => assert_eq!(handle, collider_handle),
+                None => panic!("collider of {:?} outlived its body", entity),
+            }
+        }
+
+        for (&sensor, &(entity, _)) in &physics.sensors {
+            match physics.bodies.get(&entity) {
+                Some(&Body::RigidBody {body_handle, ..}) => {
+                    assert_eq!(physics.world.collider_body_handle(sensor), Some(body_handle));
+                },
+                _ => panic!("sensor of {:?} outlived its rigid body", entity),
+            }
+        }
+
+        assert_eq!(physics.bodies.len(), (&*entities, &bounding_boxes).join().count());
+        assert_eq!(physics.colliders.len(), physics.bodies.len());
+        assert_eq!(physics.entity_sensors.values().map(Vec::len).sum::<usize>(), physics.sensors.len());
+        assert_eq!(physics.sensors.len(), 4 * (&*entities, &bounding_boxes, &densities, &collisions).join().count());
+        // Nothing is left behind in the physics engine either
+        assert_eq!(physics.world.colliders().count(), physics.colliders.len() + physics.sensors.len());
+    }
+
+    #[test]
+    fn spawning_and_despawning_keeps_bodies_in_sync() {
+        let (mut physics, mut world) = setup();
+
+        let mut alive = Vec::new();
+        let mut deleted_ids = HashSet::new();
+        let mut reused_ids = 0;
+        for round in 0..20 {
+            // New entities are created before the physics engine runs again, so they can reuse
+            // the indexes of entities that are still in it
+            let deleted: Vec<_> = alive.iter()
+                .enumerate()
+                .filter(|&(i, _)| (i + round) % 2 == 0)
+                .map(|(_, &entity)| entity)
+                .collect();
+            alive.retain(|entity| !deleted.contains(entity));
+            world.delete_entities(&deleted).unwrap();
+            world.maintain();
+            deleted_ids.extend(deleted.iter().map(|entity| entity.id()));
+
+            for i in 0..(round % 4 + 3) {
+                let entity = spawn(&mut world, round + i, (alive.len() * 40) as f64);
+                if deleted_ids.contains(&entity.id()) {
+                    reused_ids += 1;
+                }
+                alive.push(entity);
+            }
+
+            physics.run_now(&world.res);
+            world.maintain();
+            assert_in_sync(&physics, &world);
+        }
+        assert!(reused_ids > 0, "no entity indexes were reused");
+
+        world.delete_entities(&alive).unwrap();
+        world.maintain();
+        physics.run_now(&world.res);
+        world.maintain();
+        assert_in_sync(&physics, &world);
+
+        assert!(physics.bodies.is_empty());
+        assert!(physics.sensors.is_empty());
+        assert!(physics.colliders.is_empty());
+        assert_eq!(physics.world.colliders().count(), 0);
+    }
+    /// Runs a single frame, handling the given snapshot request at the end of it
+    fn step(physics: &mut Physics, world: &mut World, request: Option<SnapshotRequest>) {
+        world.write_resource::<Snapshots>().request = request;
+        physics.run_now(&world.res);
+        world.maintain();
+        world.write_resource::<Snapshots>().request = None;
+    }
+
+    #[test]
+    fn restoring_does_not_start_collisions_that_were_already_touching() {
+        let (mut physics, mut world) = setup();
+        let mut reader = world.write_resource::<EventChannel<CollisionEvent>>().register_reader();
+
+        // A rigid body resting on a static collider
+        spawn(&mut world, 0, 0.0);
+        world.create_entity()
+            .with(Position(Vec2D::new(0.0, -16.0)))
+            .with(BoundingBox {width: 16, height: 16})
+            .with(Density(1.0))
+            .with(Velocity(Vec2D::zeros()))
+            .build();
+
+        for _ in 0..30 {
+            step(&mut physics, &mut world, None);
+        }
+        step(&mut physics, &mut world, Some(SnapshotRequest::Save));
+        for _ in 0..10 {
+            step(&mut physics, &mut world, None);
+        }
+        world.read_resource::<EventChannel<CollisionEvent>>().read(&mut reader).count();
+
+        step(&mut physics, &mut world, Some(SnapshotRequest::Restore));
+        for _ in 0..10 {
+            step(&mut physics, &mut world, None);
+        }
+        let started = world.read_resource::<EventChannel<CollisionEvent>>().read(&mut reader)
+            .filter(|event| match **event {
+                CollisionEvent::Started {..} => true,
+                CollisionEvent::Stopped {..} => false,
+            })
+            .count();
+        assert_eq!(started, 0);
+    }
+}
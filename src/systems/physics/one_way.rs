@@ -0,0 +1,52 @@
+use std::{
+    f64,
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+use nalgebra::Point2;
+use nphysics2d::object::{Collider, ColliderData, ColliderHandle};
+use ncollide2d::broad_phase::BroadPhasePairFilter;
+
+/// Pairs of colliders that should currently pass through each other. The first collider in each
+/// pair is attached to a body and the second is a one-way boundary.
+pub type PassingColliders = Arc<RwLock<HashSet<(ColliderHandle, ColliderHandle)>>>;
+
+/// Stops the physics engine from detecting any contacts or proximities between the pairs of
+/// colliders that are currently passing through each other.
+///
+/// The pairs are shared with the physics system so that it can update them before each step.
+pub struct OneWayFilter {
+    pub passing: PassingColliders,
+}
+
+impl BroadPhasePairFilter<f64, ColliderData<f64>> for OneWayFilter {
+    fn is_pair_valid(&self, collider1: &Collider<f64>, collider2: &Collider<f64>) -> bool {
+        let passing = self.passing.read().expect("bug: lock on passing colliders was poisoned");
+        let (handle1, handle2) = (collider1.handle(), collider2.handle());
+        !passing.contains(&(handle1, handle2)) && !passing.contains(&(handle2, handle1))
+    }
+}
+
+/// Returns the highest y coordinate (remember that +y is downwards) of the given line at the given
+/// x coordinate, or None if the line does not extend to that x coordinate
+pub fn line_height_at(points: &[Point2<f64>], x: f64) -> Option<f64> {
+    points.windows(2).filter_map(|segment| {
+        let (left, right) = if segment[0].x <= segment[1].x {
+            (segment[0], segment[1])
+        } else {
+            (segment[1], segment[0])
+        };
+
+        if x < left.x || x > right.x {
+            None
+        }
+        else if right.x - left.x < f64::EPSILON {
+            // Vertical segment
+            Some(left.y.min(right.y))
+        }
+        else {
+            Some(left.y + (x - left.x) / (right.x - left.x) * (right.y - left.y))
+        }
+    }).fold(None, |highest: Option<f64>, y| Some(highest.map_or(y, |highest| highest.min(y))))
+}
@@ -0,0 +1,125 @@
+use std::cmp::Ordering;
+
+use specs::{Entity, Join, WriteStorage, Entities};
+use nalgebra::Point2;
+use ncollide2d::{
+    query::Ray,
+    world::CollisionGroups,
+};
+
+use components::{Position, PreviousPosition, Velocity, Projectile};
+use resources::ProjectileHit;
+use math::Vec2D;
+
+use super::Physics;
+use super::layers::body_groups;
+
+impl Physics {
+    /// Moves every projectile along its path and returns everything that was hit. Must be called
+    /// after the world has been stepped so that projectiles hit bodies where they are now.
+    pub(super) fn move_projectiles(
+        &self,
+        entities: &Entities,
+        projectiles: &mut WriteStorage<Projectile>,
+        positions: &mut WriteStorage<Position>,
+        previous_positions: &mut WriteStorage<PreviousPosition>,
+        velocities: &WriteStorage<Velocity>,
+    ) -> Vec<ProjectileHit> {
+        let timestep = self.world.timestep();
+        move_projectiles(timestep, entities, projectiles, positions, previous_positions, velocities, |projectile, ray, distance| {
+            // Projectiles only hit what the body of their owner would collide with
+            let groups = projectile.owner
+                .and_then(|owner| self.layers.get(&owner))
+                .map_or_else(CollisionGroups::new, |&layer| body_groups(&self.collision_rules, layer));
+            self.world.collision_world().interferences_with_ray(ray, &groups)
+                .filter(|&(_, ref intersection)| intersection.toi <= distance)
+                .filter_map(|(collider, intersection)| {
+                    let handle = collider.handle();
+                    // Sensors and one-way boundaries are never hit
+                    if self.sensors.contains_key(&handle) || self.trigger_sensors.contains_key(&handle)
+                        || self.overlap_sensors.contains_key(&handle)
+                        || self.one_way_boundaries.contains_key(&handle) {
+                        return None;
+                    }
+
+                    // Colliders that don't belong to an entity are the static geometry of the map
+                    let target = self.colliders.get(&handle).cloned();
+                    Some((intersection.toi, intersection.normal, target))
+                })
+                .collect()
+        })
+    }
+}
+
+/// Moves every projectile along its path, deletes the ones that stopped or ran out of lifetime
+/// and returns everything that was hit. Shared by every physics system, which each provide
+/// `cast_ray` to find the (distance, normal, target) of everything that a projectile's ray hits
+/// within the given distance. The target is None for the static geometry of the map.
+pub(in systems) fn move_projectiles<F>(
+    timestep: f64,
+    entities: &Entities,
+    projectiles: &mut WriteStorage<Projectile>,
+    positions: &mut WriteStorage<Position>,
+    previous_positions: &mut WriteStorage<PreviousPosition>,
+    velocities: &WriteStorage<Velocity>,
+    mut cast_ray: F,
+) -> Vec<ProjectileHit>
+    where F: FnMut(&Projectile, &Ray<f64>, f64) -> Vec<(f64, Vec2D, Option<Entity>)>,
+{
+    let mut hits = Vec::new();
+    for (entity, projectile, Position(position), &Velocity(vel)) in (&**entities, &mut *projectiles, &mut *positions, velocities).join() {
+        let start = *position;
+        let displacement = vel * timestep;
+        let distance = displacement.norm();
+
+        let mut end = start + displacement;
+        let mut stopped = false;
+        if distance > 0.0 {
+            let ray = Ray::new(Point2::from_coordinates(start), displacement / distance);
+
+            let mut intersections: Vec<_> = cast_ray(projectile, &ray, distance).into_iter()
+                .filter(|&(_, _, target)| match target {
+                    Some(target) => projectile.owner != Some(target) && !projectile.hit.contains(&target),
+                    None => true,
+                })
+                .collect();
+            intersections.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+            for (toi, normal, target) in intersections {
+                let point = start + ray.dir * toi;
+                hits.push(ProjectileHit {
+                    projectile: entity,
+                    owner: projectile.owner,
+                    target,
+                    point,
+                    normal,
+                    damage: projectile.damage,
+                });
+
+                match target {
+                    Some(target) if projectile.pierce > 0 => {
+                        projectile.pierce -= 1;
+                        projectile.hit.push(target);
+                    },
+                    _ => {
+                        end = point;
+                        stopped = true;
+                        break;
+                    },
+                }
+            }
+        }
+
+        projectile.lifetime = projectile.lifetime.saturating_sub(1);
+        if stopped || projectile.lifetime == 0 {
+            entities.delete(entity)
+                .expect("bug: projectile should still be alive");
+        }
+
+        previous_positions.insert(entity, PreviousPosition(*position))
+            .expect("bug: projectile should still be alive");
+        *position = end;
+    }
+
+    hits
+}